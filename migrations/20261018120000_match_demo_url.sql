@@ -0,0 +1,6 @@
+-- Add migration script here
+alter table matches
+    add column demo_url TEXT;
+
+create index match_stats_match_id_idx on match_stats (match_id);
+create index match_stats_steam_id_idx on match_stats (steam_id);
@@ -0,0 +1,6 @@
+-- Add migration script here
+alter table matches
+    add column dathost_match_id  TEXT UNIQUE,
+    add column server_id         TEXT,
+    add column server_stopped_at TIMESTAMPTZ,
+    add column summary_posted_at TIMESTAMPTZ;
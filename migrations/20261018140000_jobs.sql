@@ -0,0 +1,19 @@
+-- Add migration script here
+alter table matches
+    add column payload JSONB;
+
+create table jobs
+(
+    id         SERIAL PRIMARY KEY,
+    match_id   INT         NOT NULL references matches (id),
+    kind       VARCHAR(20) NOT NULL,
+    status     VARCHAR(10) NOT NULL DEFAULT 'pending',
+    attempts   INT         NOT NULL DEFAULT 0,
+    run_at     TIMESTAMPTZ NOT NULL,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    unique (match_id, kind)
+);
+
+create index jobs_pending_idx on jobs (run_at) where status = 'pending';
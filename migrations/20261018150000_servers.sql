@@ -0,0 +1,10 @@
+-- Add migration script here
+create table servers
+(
+    id               SERIAL PRIMARY KEY,
+    server_id        TEXT        NOT NULL UNIQUE,
+    name             TEXT,
+    ingest_token     TEXT        NOT NULL,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    token_rotated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
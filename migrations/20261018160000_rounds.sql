@@ -0,0 +1,30 @@
+-- Add migration script here
+create table rounds
+(
+    id           SERIAL PRIMARY KEY,
+    match_id     INT references matches (id),
+    server_id    TEXT        NOT NULL,
+    round_number INT         NOT NULL,
+    winner       VARCHAR(10) NOT NULL,
+    win_reason   TEXT        NOT NULL,
+    ct_score     INT         NOT NULL,
+    t_score      INT         NOT NULL,
+    started_at   TIMESTAMPTZ,
+    ended_at     TIMESTAMPTZ NOT NULL
+);
+
+create index rounds_match_id_idx on rounds (match_id);
+create index rounds_unlinked_idx on rounds (server_id) where match_id is null;
+
+create table kill_events
+(
+    id                SERIAL PRIMARY KEY,
+    round_id          INT         NOT NULL references rounds (id) on delete cascade,
+    attacker_steam_id BIGINT,
+    victim_steam_id   BIGINT,
+    weapon            VARCHAR(50) NOT NULL,
+    headshot          BOOLEAN     NOT NULL,
+    killed_at         TIMESTAMPTZ NOT NULL
+);
+
+create index kill_events_round_id_idx on kill_events (round_id);
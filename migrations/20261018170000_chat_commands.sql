@@ -0,0 +1,11 @@
+-- Add migration script here
+create table chat_commands
+(
+    name             TEXT PRIMARY KEY,
+    aliases          TEXT[]      NOT NULL DEFAULT '{}',
+    role             TEXT        NOT NULL DEFAULT 'player',
+    cooldown_seconds INT         NOT NULL DEFAULT 0,
+    lines            TEXT[]      NOT NULL DEFAULT '{}',
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at       TIMESTAMPTZ NOT NULL DEFAULT now()
+);
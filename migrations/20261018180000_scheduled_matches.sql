@@ -0,0 +1,14 @@
+-- Add migration script here
+create table scheduled_matches
+(
+    id               SERIAL PRIMARY KEY,
+    dathost_match_id TEXT        NOT NULL UNIQUE,
+    server_id        TEXT        NOT NULL,
+    map              VARCHAR(50) NOT NULL,
+    team1_name       TEXT        NOT NULL,
+    team2_name       TEXT        NOT NULL,
+    connect          TEXT        NOT NULL,
+    request          JSONB       NOT NULL,
+    match_id         INT references matches (id),
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table matches
+    add column cancel_reason TEXT;
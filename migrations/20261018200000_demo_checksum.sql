@@ -0,0 +1,4 @@
+-- Add migration script here
+alter table matches
+    add column demo_size   BIGINT,
+    add column demo_sha256 TEXT;
@@ -0,0 +1,10 @@
+-- Add migration script here
+alter table matches
+    add column demo_key        TEXT,
+    add column keep_demo       BOOLEAN NOT NULL DEFAULT false,
+    add column demo_deleted_at TIMESTAMPTZ;
+
+update matches
+set demo_key = dathost_match_id || '.dem'
+where demo_url is not null
+  and dathost_match_id is not null;
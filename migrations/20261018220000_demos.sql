@@ -0,0 +1,29 @@
+-- Add migration script here
+create table demos
+(
+    id          SERIAL PRIMARY KEY,
+    match_id    INT         NOT NULL UNIQUE references matches (id),
+    object_key  TEXT        NOT NULL,
+    size        BIGINT,
+    sha256      TEXT,
+    state       VARCHAR(10) NOT NULL DEFAULT 'stored',
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    deleted_at  TIMESTAMPTZ
+);
+
+insert into demos (match_id, object_key, size, sha256, state, uploaded_at, deleted_at)
+select id,
+       demo_key,
+       demo_size,
+       demo_sha256,
+       case when demo_deleted_at is null then 'stored' else 'deleted' end,
+       completed_at,
+       demo_deleted_at
+from matches
+where demo_key is not null;
+
+alter table matches
+    drop column demo_key,
+    drop column demo_size,
+    drop column demo_sha256,
+    drop column demo_deleted_at;
@@ -0,0 +1,7 @@
+-- Add migration script here
+alter table demos
+    add column format           VARCHAR(10),
+    add column map_name         TEXT,
+    add column server_name      TEXT,
+    add column playback_ticks   INT,
+    add column playback_seconds FLOAT4;
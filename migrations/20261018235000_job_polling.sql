@@ -0,0 +1,3 @@
+-- Add migration script here
+alter table jobs
+    add column seen_size BIGINT;
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- size and sha256 were taken over the demo as streamed from the server, which is not
+-- the stored object once it is compressed
+alter table demos
+    rename column size to demo_size;
+alter table demos
+    rename column sha256 to demo_sha256;
+alter table demos
+    add column object_size   BIGINT,
+    add column object_sha256 TEXT;
+
+update demos
+set object_size   = demo_size,
+    object_sha256 = demo_sha256
+where object_key like '%.dem';
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- a file on the server that is not a demo is recorded without an object in the bucket
+alter table demos
+    alter column object_key drop not null;
@@ -2,19 +2,37 @@ use axum::http;
 use axum::http::{Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
+use base64::engine::general_purpose;
+use base64::Engine;
+use rand::RngCore;
 use std::env;
 
+/// Compares two secrets without short-circuiting on the first differing byte
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A random URL safe token for authenticating game servers
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
 pub async fn auth<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusCode> {
     tracing::debug!("authenticating");
     let Ok(token) = env::var("AUTH_TOKEN") else {
-        return Ok(next.run(req).await)
+        return Ok(next.run(req).await);
     };
     let auth_header = req
         .headers()
         .get(http::header::AUTHORIZATION)
         .and_then(|header| header.to_str().ok());
 
-    return match auth_header {
+    match auth_header {
         Some(auth_header) => {
             if token == auth_header.replace("TOKEN ", "") {
                 Ok(next.run(req).await)
@@ -23,5 +41,5 @@ pub async fn auth<B>(req: Request<B>, next: Next<B>) -> Result<Response, StatusC
             }
         }
         None => Err(StatusCode::UNAUTHORIZED),
-    };
+    }
 }
@@ -0,0 +1,450 @@
+use crate::db::get_match_captains;
+use crate::errors::Error;
+use crate::live::{Pause, PauseKind};
+use crate::logs::LogPlayer;
+use crate::models::{ChatCommand, DathostFile, Role, ServerId};
+use crate::utils::env_or;
+use crate::AppState;
+use std::collections::HashSet;
+use std::env;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// Handles a chat message typed on a server, anything that is not a command is ignored
+pub async fn handle_chat(
+    state: &AppState,
+    server_id: &ServerId,
+    player: &LogPlayer,
+    message: &str,
+) -> Result<(), Error> {
+    let mut args = message.split_whitespace();
+    let Some(command) = args
+        .next()
+        .and_then(|c| c.strip_prefix('!'))
+        .and_then(|name| state.commands.get(name))
+    else {
+        return Ok(());
+    };
+    if command.role > Role::Player && role_of(state, server_id, player).await? < command.role {
+        let msg = format!("{}, you can not use !{}.", player.name, command.name);
+        return say(state, server_id, &msg).await;
+    }
+    if !start_cooldown(state, server_id, &command) {
+        return Ok(());
+    }
+    if !command.lines.is_empty() {
+        let args = args.collect::<Vec<_>>().join(" ");
+        for line in &command.lines {
+            let line = line
+                .replace("{player}", &console_arg(&player.name))
+                .replace("{args}", &console_arg(&args));
+            send(state, server_id, &line).await?;
+        }
+        return Ok(());
+    }
+    match command.name.as_str() {
+        "tech" => pause(state, server_id, PauseKind::Technical).await,
+        "pause" => pause(state, server_id, PauseKind::Regular).await,
+        "tac" => tactical_pause(state, server_id, player).await,
+        "unpause" => unpause(state, server_id, player).await,
+        "ready" => ready(state, server_id, player, true).await,
+        "unready" => ready(state, server_id, player, false).await,
+        "forcestart" => force_start(state, server_id).await,
+        "restore" => {
+            let Some(round) = args.next().and_then(|r| r.parse().ok()) else {
+                return say(state, server_id, "Usage: !restore <round>").await;
+            };
+            match restore_backup(state, server_id, round).await {
+                Ok(_) => Ok(()),
+                Err(Error::NotFound) => {
+                    let msg = format!("There is no backup for round {}.", round);
+                    say(state, server_id, &msg).await
+                }
+                Err(e) => Err(e),
+            }
+        }
+        "score" => {
+            let (ct_score, t_score) = {
+                let live = state.live.lock();
+                live.get(server_id)
+                    .map_or((0, 0), |s| (s.ct_score, s.t_score))
+            };
+            say(
+                state,
+                server_id,
+                &format!("CT {} - {} T", ct_score, t_score),
+            )
+            .await
+        }
+        "help" => {
+            let role = role_of(state, server_id, player).await?;
+            let names: Vec<_> = state
+                .commands
+                .list()
+                .into_iter()
+                .filter(|c| c.role <= role)
+                .map(|c| format!("!{}", c.name))
+                .collect();
+            say(state, server_id, &format!("Commands: {}", names.join(" "))).await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns false while the command is cooling down on this server
+fn start_cooldown(state: &AppState, server_id: &ServerId, command: &ChatCommand) -> bool {
+    if command.cooldown_seconds == 0 {
+        return true;
+    }
+    let mut live = state.live.lock();
+    let cooldowns = &mut live.entry(server_id.clone()).or_default().cooldowns;
+    let now = Instant::now();
+    let cooldown = Duration::from_secs(command.cooldown_seconds as u64);
+    if cooldowns
+        .get(&command.name)
+        .is_some_and(|used| now.duration_since(*used) < cooldown)
+    {
+        return false;
+    }
+    cooldowns.insert(command.name.clone(), now);
+    true
+}
+
+/// Keeps player supplied text from ending the console line or starting another
+fn console_arg(arg: &str) -> String {
+    arg.chars()
+        .filter(|c| !matches!(c, ';' | '"' | '\n' | '\r'))
+        .collect()
+}
+
+pub async fn send(state: &AppState, server_id: &ServerId, line: &str) -> Result<(), Error> {
+    let status_code = state
+        .dathost
+        .send_console_msg(server_id, line.to_string())
+        .await?;
+    if status_code.as_u16() != 200 {
+        tracing::error!("send console msg status: {}", status_code)
+    }
+    Ok(())
+}
+
+pub async fn say(state: &AppState, server_id: &ServerId, msg: &str) -> Result<(), Error> {
+    send(state, server_id, &format!("say {}", msg)).await
+}
+
+/// Starts a pause, returns `None` if the match is already paused
+fn start_pause(state: &AppState, server_id: &ServerId, kind: PauseKind) -> Option<u64> {
+    let mut live = state.live.lock();
+    let server = live.entry(server_id.clone()).or_default();
+    if server.pause.is_some() {
+        return None;
+    }
+    server.pauses_started += 1;
+    server.pause = Some(Pause {
+        id: server.pauses_started,
+        kind,
+        unpause_requests: HashSet::new(),
+    });
+    Some(server.pauses_started)
+}
+
+async fn pause(state: &AppState, server_id: &ServerId, kind: PauseKind) -> Result<(), Error> {
+    if start_pause(state, server_id, kind).is_none() {
+        return say(state, server_id, "The match is already paused.").await;
+    }
+    send(state, server_id, "mp_pause_match").await?;
+    say(
+        state,
+        server_id,
+        "Both teams must type !unpause to resume the match. Be sure both teams are ready before unpausing.",
+    )
+    .await
+}
+
+async fn tactical_pause(
+    state: &AppState,
+    server_id: &ServerId,
+    player: &LogPlayer,
+) -> Result<(), Error> {
+    let Some(side) = player.side.filter(|s| s.opponent().is_some()) else {
+        return Ok(());
+    };
+    let limit = env_or::<u32>("TAC_PAUSE_LIMIT", 3);
+    let (team, used) = {
+        let mut live = state.live.lock();
+        let server = live.entry(server_id.clone()).or_default();
+        let team = server.team_on(
+            side,
+            env_or::<u32>("MAX_ROUNDS", 24) as i32,
+            env_or::<u32>("OVERTIME_MAX_ROUNDS", 6) as i32,
+        );
+        let used = team.and_then(|t| server.tactical_pauses_used.get(&t).copied());
+        (team, used.unwrap_or(0))
+    };
+    let Some(team) = team else {
+        return Ok(());
+    };
+    if used >= limit {
+        let msg = format!("{} has no tactical timeouts left.", side.name());
+        return say(state, server_id, &msg).await;
+    }
+    let Some(pause_id) = start_pause(state, server_id, PauseKind::Tactical(side)) else {
+        return say(state, server_id, "The match is already paused.").await;
+    };
+    state
+        .live
+        .lock()
+        .entry(server_id.clone())
+        .or_default()
+        .tactical_pauses_used
+        .insert(team, used + 1);
+    let seconds = env_or::<u32>("TAC_PAUSE_SECONDS", 30);
+    send(state, server_id, "mp_pause_match").await?;
+    let msg = format!(
+        "{} tactical timeout {}/{}, the match resumes in {} seconds.",
+        side.name(),
+        used + 1,
+        limit,
+        seconds
+    );
+    say(state, server_id, &msg).await?;
+
+    let state = state.clone();
+    let server_id = server_id.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_secs(seconds as u64)).await;
+        let expired = {
+            let mut live = state.live.lock();
+            let server = live.entry(server_id.clone()).or_default();
+            let expired = server.pause.as_ref().is_some_and(|p| p.id == pause_id);
+            if expired {
+                server.pause = None;
+            }
+            expired
+        };
+        if expired {
+            if let Err(e) = send(&state, &server_id, "mp_unpause_match").await {
+                tracing::error!("unable to end tactical timeout on {}: {}", server_id, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn unpause(state: &AppState, server_id: &ServerId, player: &LogPlayer) -> Result<(), Error> {
+    let waiting_on = {
+        let mut live = state.live.lock();
+        let server = live.entry(server_id.clone()).or_default();
+        let waiting_on = match (&mut server.pause, player.side) {
+            // no pause we know of, e.g. one started from the console
+            (None, _) => None,
+            // the team that called a tactical timeout can end it early
+            (Some(pause), Some(side)) if pause.kind == PauseKind::Tactical(side) => None,
+            (Some(pause), Some(side)) if side.opponent().is_some() => {
+                pause.unpause_requests.insert(side);
+                side.opponent()
+                    .filter(|other| !pause.unpause_requests.contains(other))
+            }
+            // spectators can not unpause
+            (Some(_), _) => return Ok(()),
+        };
+        if waiting_on.is_none() {
+            server.pause = None;
+        }
+        waiting_on
+    };
+    match waiting_on {
+        Some(other) => {
+            let msg = format!(
+                "{} is ready to resume, waiting for {} to type !unpause.",
+                player.side.map_or("", |s| s.name()),
+                other.name()
+            );
+            say(state, server_id, &msg).await
+        }
+        None => send(state, server_id, "mp_unpause_match").await,
+    }
+}
+
+async fn ready(
+    state: &AppState,
+    server_id: &ServerId,
+    player: &LogPlayer,
+    is_ready: bool,
+) -> Result<(), Error> {
+    let Some(steam_id) = player.steam_id else {
+        return Ok(());
+    };
+    // spectators do not count towards the players needed
+    if player.side.and_then(|s| s.opponent()).is_none() {
+        return Ok(());
+    }
+    let required = env_or::<u32>("READY_PLAYERS", 10) as usize;
+    let (ready_count, start_timer) = {
+        let mut live = state.live.lock();
+        let server = live.entry(server_id.clone()).or_default();
+        if server.started {
+            (None, false)
+        } else {
+            if is_ready {
+                server.ready.insert(steam_id);
+            } else {
+                server.ready.remove(&steam_id);
+            }
+            let start_timer = is_ready && !server.ready_timer_started;
+            server.ready_timer_started |= start_timer;
+            (Some(server.ready.len()), start_timer)
+        }
+    };
+    let Some(ready_count) = ready_count else {
+        return say(state, server_id, "The match has already started.").await;
+    };
+    if ready_count >= required {
+        return start_match(state, server_id, "All players are ready").await;
+    }
+    let msg = format!(
+        "{} is {}ready, {} of {} players not ready yet.",
+        player.name,
+        if is_ready { "" } else { "not " },
+        required - ready_count,
+        required
+    );
+    say(state, server_id, &msg).await?;
+
+    let timeout = env_or::<u32>("READY_TIMEOUT_SECONDS", 0);
+    if start_timer && timeout > 0 {
+        let msg = format!("The match starts in {} seconds at the latest.", timeout);
+        say(state, server_id, &msg).await?;
+        let state = state.clone();
+        let server_id = server_id.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(timeout as u64)).await;
+            let started = state.live.lock().get(&server_id).is_none_or(|s| s.started);
+            if started {
+                return;
+            }
+            if let Err(e) = start_match(&state, &server_id, "Ready up timed out").await {
+                tracing::error!("unable to start match on {}: {}", server_id, e);
+                // the next !ready starts another timer
+                if let Some(server) = state.live.lock().get_mut(&server_id) {
+                    server.ready_timer_started = false;
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn force_start(state: &AppState, server_id: &ServerId) -> Result<(), Error> {
+    let started = state.live.lock().get(server_id).is_some_and(|s| s.started);
+    if started {
+        return say(state, server_id, "The match has already started.").await;
+    }
+    start_match(state, server_id, "An admin started the match").await
+}
+
+/// Ends warmup, the match only counts as started once the server accepted the command
+/// so a failed attempt can be retried with the next `!ready` or `!forcestart`
+async fn start_match(state: &AppState, server_id: &ServerId, reason: &str) -> Result<(), Error> {
+    tracing::info!("starting match on {}: {}", server_id, reason);
+    let status = state
+        .dathost
+        .send_console_msg(server_id, "mp_warmup_end".to_string())
+        .await?;
+    if !status.is_success() {
+        return Err(Error::Dathost(status));
+    }
+    state
+        .live
+        .lock()
+        .entry(server_id.clone())
+        .or_default()
+        .started = true;
+    say(
+        state,
+        server_id,
+        &format!("{}, the match is starting.", reason),
+    )
+    .await
+}
+
+/// Lists the round backups the server has written for the current match
+pub async fn list_backups(
+    state: &AppState,
+    server_id: &ServerId,
+) -> Result<Vec<DathostFile>, Error> {
+    let mut backups: Vec<_> = state
+        .dathost
+        .list_files(server_id, "")
+        .await?
+        .into_iter()
+        .filter(|f| backup_file_name(&f.path).is_some())
+        .collect();
+    backups.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(backups)
+}
+
+/// Restores the match to the start of `round`, counted from 1 like the scoreboard.
+/// The server pauses after loading a backup, so both teams have to `!unpause`.
+pub async fn restore_backup(
+    state: &AppState,
+    server_id: &ServerId,
+    round: u32,
+) -> Result<String, Error> {
+    // backup_round00.txt is written at the start of the first round
+    let wanted = format!(
+        "backup_round{:02}.txt",
+        round.checked_sub(1).ok_or(Error::NotFound)?
+    );
+    let file = list_backups(state, server_id)
+        .await?
+        .into_iter()
+        .filter_map(|f| backup_file_name(&f.path).map(str::to_string))
+        .find(|name| *name == wanted)
+        .ok_or(Error::NotFound)?;
+    tracing::info!("restoring {} on {}", file, server_id);
+    send(
+        state,
+        server_id,
+        &format!("mp_backup_restore_load_file {}", file),
+    )
+    .await?;
+    start_pause(state, server_id, PauseKind::Technical);
+    let msg = format!(
+        "Restored round {}. Both teams must type !unpause to resume the match.",
+        round
+    );
+    say(state, server_id, &msg).await?;
+    Ok(file)
+}
+
+fn backup_file_name(path: &str) -> Option<&str> {
+    let name = path.rsplit('/').next()?;
+    (name.starts_with("backup_round") && name.ends_with(".txt")).then_some(name)
+}
+
+/// Admins are a comma separated list of 64-bit Steam ids in `ADMIN_STEAM_IDS`,
+/// captains are the ones named when the match on the server was created
+pub async fn role_of(
+    state: &AppState,
+    server_id: &ServerId,
+    player: &LogPlayer,
+) -> Result<Role, Error> {
+    let Some(steam_id) = player.steam_id else {
+        return Ok(Role::Player);
+    };
+    let admin = env::var("ADMIN_STEAM_IDS").is_ok_and(|ids| {
+        ids.split(',')
+            .any(|id| id.trim().parse::<u64>() == Ok(steam_id))
+    });
+    if admin {
+        return Ok(Role::Admin);
+    }
+    if get_match_captains(&state.db, server_id)
+        .await?
+        .contains(&steam_id)
+    {
+        return Ok(Role::Captain);
+    }
+    Ok(Role::Player)
+}
@@ -4,9 +4,11 @@ use base64::Engine;
 use std::{env, time::Duration};
 
 use bytes::Bytes;
+use futures::Stream;
+use reqwest::multipart::{Form, Part};
 use reqwest::{Client, Result, StatusCode};
 
-use crate::models::ServerId;
+use crate::models::{CreatedDathostMatch, DathostFile, DathostServer, NewDathostMatch, ServerId};
 #[derive(Clone)]
 pub struct DathostClient(Client);
 
@@ -38,18 +40,81 @@ impl DathostClient {
 
     pub async fn get_file(&self, server_id: &ServerId, path: &str) -> Result<Bytes> {
         self.0
-            .get(&format!(
+            .get(format!(
                 "https://dathost.net/api/0.1/game-servers/{server_id}/files/{path}"
             ))
             .send()
             .await?
+            .error_for_status()?
             .bytes()
             .await
     }
+    /// Reads a file from the server, `None` if it does not exist
+    pub async fn find_file(&self, server_id: &ServerId, path: &str) -> Result<Option<Bytes>> {
+        let response = self
+            .0
+            .get(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/files/{path}"
+            ))
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(response.error_for_status()?.bytes().await?))
+    }
+    /// Streams a file from the server instead of buffering it
+    pub async fn get_file_stream(
+        &self,
+        server_id: &ServerId,
+        path: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes>>> {
+        Ok(self
+            .0
+            .get(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/files/{path}"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes_stream())
+    }
+    /// Writes a file on the server, replacing it if it exists
+    pub async fn upload_file(
+        &self,
+        server_id: &ServerId,
+        path: &str,
+        contents: Vec<u8>,
+    ) -> Result<StatusCode> {
+        let file_name = path.rsplit('/').next().unwrap_or(path).to_string();
+        let form = Form::new().part("file", Part::bytes(contents).file_name(file_name));
+        Ok(self
+            .0
+            .post(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/files/{path}"
+            ))
+            .multipart(form)
+            .send()
+            .await?
+            .status())
+    }
+    /// Lists the files on a server, `path` is the directory to list
+    pub async fn list_files(&self, server_id: &ServerId, path: &str) -> Result<Vec<DathostFile>> {
+        self.0
+            .get(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/files"
+            ))
+            .query(&[("path", path), ("with_filesizes", "true")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
     pub async fn send_console_msg(&self, server_id: &ServerId, msg: String) -> Result<StatusCode> {
         Ok(self
             .0
-            .post(&format!(
+            .post(format!(
                 "https://dathost.net/api/0.1/game-servers/{server_id}/console"
             ))
             .form(&[("line", &msg)])
@@ -57,10 +122,94 @@ impl DathostClient {
             .await?
             .status())
     }
+    pub async fn get_server(&self, server_id: &ServerId) -> Result<DathostServer> {
+        self.0
+            .get(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    pub async fn list_servers(&self) -> Result<Vec<DathostServer>> {
+        self.0
+            .get("https://dathost.net/api/0.1/game-servers")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    /// Copies a server and its files, returning the new server
+    pub async fn duplicate_server(&self, server_id: &ServerId) -> Result<DathostServer> {
+        self.0
+            .post(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/duplicate"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    pub async fn delete_server(&self, server_id: &ServerId) -> Result<StatusCode> {
+        Ok(self
+            .0
+            .delete(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}"
+            ))
+            .send()
+            .await?
+            .status())
+    }
+    pub async fn start_server(&self, server_id: &ServerId) -> Result<StatusCode> {
+        Ok(self
+            .0
+            .post(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/start"
+            ))
+            .send()
+            .await?
+            .status())
+    }
+    /// Reboots a running server through Dathost instead of a separate stop and start
+    pub async fn restart_server(&self, server_id: &ServerId) -> Result<StatusCode> {
+        Ok(self
+            .0
+            .post(format!(
+                "https://dathost.net/api/0.1/game-servers/{server_id}/reboot"
+            ))
+            .send()
+            .await?
+            .status())
+    }
+    pub async fn create_match(&self, new_match: &NewDathostMatch) -> Result<CreatedDathostMatch> {
+        self.0
+            .post("https://dathost.net/api/0.1/cs2-matches")
+            .json(new_match)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+    /// Cancels a match that has not ended, freeing its server
+    pub async fn cancel_match(&self, match_id: &str) -> Result<StatusCode> {
+        Ok(self
+            .0
+            .post(format!(
+                "https://dathost.net/api/0.1/cs2-matches/{match_id}/cancel"
+            ))
+            .send()
+            .await?
+            .status())
+    }
     pub async fn stop_server(&self, server_id: &ServerId) -> Result<StatusCode> {
         Ok(self
             .0
-            .post(&format!(
+            .post(format!(
                 "https://dathost.net/api/0.1/game-servers/{server_id}/stop"
             ))
             .send()
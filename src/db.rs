@@ -1,35 +1,76 @@
+use crate::demo::DemoHeader;
 use crate::errors::Error;
-use crate::models::{DathostMatch, DathostMatchEnd};
+use crate::live::{CompletedRound, KillEvent};
+use crate::models::{
+    ChatCommand, Checksum, CreateMatch, DathostMatch, DathostMatchEnd, DathostMatchId, Demo, Job,
+    JobKind, KillDetails, MatchListParams, MatchPlayerStats, PlayerStats, PlayerStatsSummary,
+    RegisteredServer, RoundDetails, ScheduledMatch, ServerId, StatsParams,
+};
 use sqlx::types::time::OffsetDateTime;
-use sqlx::{PgExecutor, PgPool};
+use sqlx::types::Json;
+use sqlx::{PgConnection, PgExecutor};
 
+/// Inserts the match, `None` if a match with the same Dathost id is already recorded
 pub async fn create_match(
     executor: impl PgExecutor<'_>,
     dathost_match: &DathostMatchEnd,
-) -> Result<DathostMatch, Error> {
+) -> Result<Option<DathostMatch>, Error> {
     Ok(sqlx::query_as!(
         DathostMatch,
-        r#"insert into matches (map, team1_score, team2_score, team1_name, team2_name, completed_at)
-            values ($1, $2, $3, $4, $5, $6) returning id, map, team1_score, team2_score, team1_name, team2_name, completed_at"#,
+        r#"insert into matches (map, team1_score, team2_score, team1_name, team2_name, completed_at,
+                dathost_match_id, server_id, payload, cancel_reason)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            on conflict (dathost_match_id) do nothing
+            returning id, map, team1_score, team2_score, team1_name, team2_name, completed_at, demo_url,
+                dathost_match_id, server_id, server_stopped_at, summary_posted_at, cancel_reason,
+                keep_demo"#,
         dathost_match.settings.map,
         dathost_match.team1.stats.score,
         dathost_match.team2.stats.score,
         dathost_match.team1.name,
         dathost_match.team2.name,
         OffsetDateTime::now_utc(),
+        dathost_match.id.0,
+        dathost_match.server_id.0,
+        Json(dathost_match) as _,
+        dathost_match.cancel_reason,
     )
-    .fetch_one(executor)
+    .fetch_optional(executor)
     .await?)
 }
 
+/// Records a finished match with its stats and links the rounds and the scheduled match
+/// ingested before it. `None` if the match is already recorded. Run it in a transaction,
+/// a failure part way through leaves the caller to roll everything back.
+pub async fn record_match(
+    conn: &mut PgConnection,
+    dathost_match: &DathostMatchEnd,
+) -> Result<Option<DathostMatch>, Error> {
+    let Some(created_match) = create_match(&mut *conn, dathost_match).await? else {
+        return Ok(None);
+    };
+    create_match_stats(&mut *conn, dathost_match, created_match.id).await?;
+    link_rounds(&mut *conn, &dathost_match.server_id, created_match.id).await?;
+    link_scheduled_match(&mut *conn, &dathost_match.id, created_match.id).await?;
+    Ok(Some(created_match))
+}
+
 pub async fn create_match_stats(
-    executor: &PgPool,
+    executor: impl PgExecutor<'_>,
     dathost_match: &DathostMatchEnd,
     match_id: i32,
 ) -> Result<(), Error> {
-    for p in &dathost_match.players {
-        sqlx::query!(
-            r#"insert into match_stats (
+    let players = &dathost_match.players;
+    let stat =
+        |f: fn(&PlayerStats) -> i32| -> Vec<i32> { players.iter().map(|p| f(&p.stats)).collect() };
+    let steam_ids: Vec<i64> = players.iter().map(|p| p.steam_id_64 as i64).collect();
+    let teams: Vec<String> = players.iter().map(|p| p.team.clone()).collect();
+    let adrs: Vec<f64> = players
+        .iter()
+        .map(|p| p.stats.damage_dealt as f64 / dathost_match.rounds_played.max(1) as f64)
+        .collect();
+    sqlx::query!(
+        r#"insert into match_stats (
        steam_id,
        match_id,
        team,
@@ -53,60 +94,944 @@ pub async fn create_match_stats(
        utility_thrown,
        utility_damage,
        n1vx_attempts,
-       n1vx_wins) 
-       values(
-       $1,
-       $2,
-       $3,
-       $4,
-       $5,
-       $6,
-       $7,
-       $8,
-       $9,
-       $10,
-       $11,
-       $12,
-       $13,
-       $14,
-       $15,
-       $16,
-       $17,
-       $18,
-       $19,
-       $20,
-       $21,
-       $22,
-       $23,
-       $24
-       );"#,
-            p.steam_id_64 as i64,
-            match_id,
-            p.team,
-            p.stats.kills,
-            p.stats.assists,
-            p.stats.deaths,
-            p.stats.damage_dealt as f64 / dathost_match.rounds_played.max(1) as f64,
-            p.stats.n2ks,
-            p.stats.n3ks,
-            p.stats.n4ks,
-            p.stats.n5ks,
-            p.stats.kills_with_headshot,
-            p.stats.kills_with_pistol,
-            p.stats.kills_with_sniper,
-            p.stats.damage_dealt,
-            p.stats.entry_attempts,
-            p.stats.entry_successes,
-            p.stats.flashes_thrown,
-            p.stats.flashes_successful,
-            p.stats.flashes_enemies_blinded,
-            p.stats.utility_thrown,
-            p.stats.utility_damage,
-            p.stats.n1v_x_attempts,
-            p.stats.n1v_x_wins,
-        )
+       n1vx_wins)
+       select steam_id, $2, team, kills, assists, deaths, adr, n2ks, n3ks, n4ks, n5ks,
+              kills_with_headshot, kills_with_pistol, kills_with_sniper, damage_dealt,
+              entry_attempts, entry_successes, flashes_thrown, flashes_successful,
+              flashes_enemies_blinded, utility_thrown, utility_damage, n1vx_attempts, n1vx_wins
+       from unnest(
+       $1::bigint[],
+       $3::varchar[],
+       $4::int[],
+       $5::int[],
+       $6::int[],
+       $7::float8[],
+       $8::int[],
+       $9::int[],
+       $10::int[],
+       $11::int[],
+       $12::int[],
+       $13::int[],
+       $14::int[],
+       $15::int[],
+       $16::int[],
+       $17::int[],
+       $18::int[],
+       $19::int[],
+       $20::int[],
+       $21::int[],
+       $22::int[],
+       $23::int[],
+       $24::int[]
+       ) as p(steam_id, team, kills, assists, deaths, adr, n2ks, n3ks, n4ks, n5ks,
+              kills_with_headshot, kills_with_pistol, kills_with_sniper, damage_dealt,
+              entry_attempts, entry_successes, flashes_thrown, flashes_successful,
+              flashes_enemies_blinded, utility_thrown, utility_damage, n1vx_attempts, n1vx_wins)"#,
+        &steam_ids,
+        match_id,
+        &teams,
+        &stat(|s| s.kills),
+        &stat(|s| s.assists),
+        &stat(|s| s.deaths),
+        &adrs,
+        &stat(|s| s.n2ks),
+        &stat(|s| s.n3ks),
+        &stat(|s| s.n4ks),
+        &stat(|s| s.n5ks),
+        &stat(|s| s.kills_with_headshot),
+        &stat(|s| s.kills_with_pistol),
+        &stat(|s| s.kills_with_sniper),
+        &stat(|s| s.damage_dealt),
+        &stat(|s| s.entry_attempts),
+        &stat(|s| s.entry_successes),
+        &stat(|s| s.flashes_thrown),
+        &stat(|s| s.flashes_successful),
+        &stat(|s| s.flashes_enemies_blinded),
+        &stat(|s| s.utility_thrown),
+        &stat(|s| s.utility_damage),
+        &stat(|s| s.n1v_x_attempts),
+        &stat(|s| s.n1v_x_wins),
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_player_stats(
+    executor: impl PgExecutor<'_>,
+    steam_id: Option<i64>,
+    params: &StatsParams,
+) -> Result<Vec<PlayerStatsSummary>, Error> {
+    Ok(sqlx::query_as!(
+        PlayerStatsSummary,
+        r#"select s.steam_id::text                                                          as "steam_id!",
+       count(*)                                                                   as "matches!",
+       sum(s.kills)                                                               as "kills!",
+       sum(s.deaths)                                                              as "deaths!",
+       sum(s.assists)                                                             as "assists!",
+       sum(s.kills)::float8 / greatest(sum(s.deaths), 1)                          as "kd!",
+       avg(s.adr)                                                                 as "adr!",
+       100.0 * sum(s.kills_with_headshot)::float8 / greatest(sum(s.kills), 1)     as "hs_pct!",
+       sum(s.n2ks)                                                                as "n2ks!",
+       sum(s.n3ks)                                                                as "n3ks!",
+       sum(s.n4ks)                                                                as "n4ks!",
+       sum(s.n5ks)                                                                as "n5ks!",
+       sum(s.entry_attempts)                                                      as "entry_attempts!",
+       sum(s.entry_successes)                                                     as "entry_successes!",
+       100.0 * sum(s.entry_successes)::float8 / greatest(sum(s.entry_attempts), 1) as "entry_rate!",
+       sum(s.flashes_thrown)                                                      as "flashes_thrown!",
+       sum(s.flashes_successful)                                                  as "flashes_successful!",
+       sum(s.flashes_enemies_blinded)                                             as "flashes_enemies_blinded!",
+       sum(s.utility_thrown)                                                      as "utility_thrown!",
+       sum(s.utility_damage)                                                      as "utility_damage!",
+       sum(s.n1vx_attempts)                                                       as "n1vx_attempts!",
+       sum(s.n1vx_wins)                                                           as "n1vx_wins!",
+       100.0 * sum(s.n1vx_wins)::float8 / greatest(sum(s.n1vx_attempts), 1)      as "n1vx_win_rate!"
+from match_stats s
+         join matches m on m.id = s.match_id
+where m.cancel_reason is null
+  and ($1::bigint is null or s.steam_id = $1)
+  and ($2::text is null or m.map = $2)
+  and ($3::timestamptz is null or m.completed_at >= $3)
+  and ($4::timestamptz is null or m.completed_at <= $4)
+group by s.steam_id
+having count(*) >= $5"#,
+        steam_id,
+        params.map,
+        params.from,
+        params.to,
+        params.min_matches.unwrap_or(1),
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+pub async fn create_demo(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+    object_key: &str,
+    demo: &Checksum,
+    object: &Checksum,
+    header: Option<&DemoHeader>,
+) -> Result<Demo, Error> {
+    Ok(sqlx::query_as!(
+        Demo,
+        r#"insert into demos (match_id, object_key, demo_size, demo_sha256, object_size, object_sha256,
+                   format, map_name, server_name, playback_ticks, playback_seconds)
+values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+returning id, match_id, object_key, demo_size, demo_sha256, object_size, object_sha256, state, uploaded_at, deleted_at,
+       format, map_name, server_name, playback_ticks, playback_seconds"#,
+        match_id,
+        object_key,
+        demo.size as i64,
+        demo.sha256,
+        object.size as i64,
+        object.sha256,
+        header.map(|h| h.format.as_str()),
+        header.and_then(|h| h.map_name.as_deref()),
+        header.and_then(|h| h.server_name.as_deref()),
+        header.and_then(|h| h.playback_ticks),
+        header.and_then(|h| h.playback_seconds),
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Records that the file on the server is not a demo, so it is not fetched again
+pub async fn create_invalid_demo(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "insert into demos (match_id, state) values ($1, 'invalid') on conflict (match_id) do nothing",
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_demo(executor: impl PgExecutor<'_>, match_id: i32) -> Result<Option<Demo>, Error> {
+    Ok(sqlx::query_as!(
+        Demo,
+        r#"select id, match_id, object_key, demo_size, demo_sha256, object_size, object_sha256, state, uploaded_at, deleted_at,
+       format, map_name, server_name, playback_ticks, playback_seconds
+from demos
+where match_id = $1"#,
+        match_id
+    )
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// The public link shown in Discord, only set when the bucket is served through `BUCKET_BASE_URL`
+pub async fn set_match_demo_url(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+    demo_url: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update matches set demo_url = $1 where id = $2",
+        demo_url,
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_match_keep_demo(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+    keep: bool,
+) -> Result<(), Error> {
+    let result = sqlx::query!(
+        "update matches set keep_demo = $1 where id = $2",
+        keep,
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Stored demos of matches completed before `cutoff` that are not kept
+pub async fn get_expired_demos(
+    executor: impl PgExecutor<'_>,
+    cutoff: OffsetDateTime,
+) -> Result<Vec<Demo>, Error> {
+    Ok(sqlx::query_as!(
+        Demo,
+        r#"select d.id, d.match_id, d.object_key, d.demo_size, d.demo_sha256, d.object_size,
+       d.object_sha256, d.state, d.uploaded_at, d.deleted_at,
+       d.format, d.map_name, d.server_name, d.playback_ticks, d.playback_seconds
+from demos d
+         join matches m on m.id = d.match_id
+where d.state = 'stored'
+  and not m.keep_demo
+  and m.completed_at < $1
+order by m.completed_at"#,
+        cutoff
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Records that a demo is gone from the bucket and drops the match's public link to it
+pub async fn set_demo_deleted(executor: impl PgExecutor<'_>, demo_id: i32) -> Result<(), Error> {
+    sqlx::query!(
+        r#"with deleted as (
+    update demos set state = 'deleted', deleted_at = $1 where id = $2 returning match_id
+)
+update matches
+set demo_url = null
+where id in (select match_id from deleted)"#,
+        OffsetDateTime::now_utc(),
+        demo_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_match(executor: impl PgExecutor<'_>, id: i32) -> Result<DathostMatch, Error> {
+    sqlx::query_as!(
+        DathostMatch,
+        r#"select id, map, team1_score, team2_score, team1_name, team2_name, completed_at, demo_url,
+                dathost_match_id, server_id, server_stopped_at, summary_posted_at, cancel_reason,
+                keep_demo
+            from matches where id = $1"#,
+        id
+    )
+    .fetch_optional(executor)
+    .await?
+    .ok_or(Error::NotFound)
+}
+
+pub async fn get_matches(
+    executor: impl PgExecutor<'_>,
+    params: &MatchListParams,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<DathostMatch>, Error> {
+    Ok(sqlx::query_as!(
+        DathostMatch,
+        r#"select id, map, team1_score, team2_score, team1_name, team2_name, completed_at, demo_url,
+                dathost_match_id, server_id, server_stopped_at, summary_posted_at, cancel_reason,
+                keep_demo
+from matches m
+where ($1::text is null or m.map = $1)
+  and ($2::text is null or m.team1_name = $2 or m.team2_name = $2)
+  and ($3::bigint is null or exists(select 1 from match_stats s where s.match_id = m.id and s.steam_id = $3))
+  and ($4::timestamptz is null or m.completed_at >= $4)
+  and ($5::timestamptz is null or m.completed_at <= $5)
+order by m.completed_at desc, m.id desc
+limit $6 offset $7"#,
+        params.map,
+        params.team,
+        params.steam_id,
+        params.from,
+        params.to,
+        limit,
+        offset,
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+pub async fn count_matches(
+    executor: impl PgExecutor<'_>,
+    params: &MatchListParams,
+) -> Result<i64, Error> {
+    Ok(sqlx::query_scalar!(
+        r#"select count(*) as "count!"
+from matches m
+where ($1::text is null or m.map = $1)
+  and ($2::text is null or m.team1_name = $2 or m.team2_name = $2)
+  and ($3::bigint is null or exists(select 1 from match_stats s where s.match_id = m.id and s.steam_id = $3))
+  and ($4::timestamptz is null or m.completed_at >= $4)
+  and ($5::timestamptz is null or m.completed_at <= $5)"#,
+        params.map,
+        params.team,
+        params.steam_id,
+        params.from,
+        params.to,
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+pub async fn get_match_stats(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<Vec<MatchPlayerStats>, Error> {
+    Ok(sqlx::query_as!(
+        MatchPlayerStats,
+        r#"select steam_id::text as "steam_id!",
+       team,
+       kills,
+       assists,
+       deaths,
+       adr,
+       n2ks,
+       n3ks,
+       n4ks,
+       n5ks,
+       kills_with_headshot,
+       kills_with_pistol,
+       kills_with_sniper,
+       damage_dealt,
+       entry_attempts,
+       entry_successes,
+       flashes_thrown,
+       flashes_successful,
+       flashes_enemies_blinded,
+       utility_thrown,
+       utility_damage,
+       n1vx_attempts,
+       n1vx_wins
+from match_stats
+where match_id = $1
+order by damage_dealt desc"#,
+        match_id
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+pub async fn get_match_by_dathost_id(
+    executor: impl PgExecutor<'_>,
+    dathost_match_id: &DathostMatchId,
+) -> Result<Option<DathostMatch>, Error> {
+    Ok(sqlx::query_as!(
+        DathostMatch,
+        r#"select id, map, team1_score, team2_score, team1_name, team2_name, completed_at, demo_url,
+                dathost_match_id, server_id, server_stopped_at, summary_posted_at, cancel_reason,
+                keep_demo
+            from matches where dathost_match_id = $1"#,
+        dathost_match_id.0
+    )
+    .fetch_optional(executor)
+    .await?)
+}
+
+pub async fn set_match_server_stopped(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update matches set server_stopped_at = $1 where id = $2",
+        OffsetDateTime::now_utc(),
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn set_match_summary_posted(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update matches set summary_posted_at = $1 where id = $2",
+        OffsetDateTime::now_utc(),
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_match_payload(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<DathostMatchEnd, Error> {
+    sqlx::query_scalar!(
+        r#"select payload as "payload: Json<DathostMatchEnd>" from matches where id = $1"#,
+        match_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .flatten()
+    .map(|payload| payload.0)
+    .ok_or(Error::NotFound)
+}
+
+/// Queues `kind` for the match. A job that already exists is left alone unless it
+/// previously failed, in which case it is reset and run again as if newly queued.
+pub async fn enqueue_job(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+    kind: JobKind,
+    run_at: OffsetDateTime,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"insert into jobs (match_id, kind, run_at)
+            values ($1, $2, $3)
+            on conflict (match_id, kind) do update
+                set status = 'pending', attempts = 0, run_at = excluded.run_at, seen_size = null,
+                    created_at = now(), updated_at = now()
+                where jobs.status = 'failed'"#,
+        match_id,
+        kind.as_str(),
+        run_at,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Claims the next due job, marking it as running so other workers skip it.
+pub async fn claim_next_job(executor: impl PgExecutor<'_>) -> Result<Option<Job>, Error> {
+    Ok(sqlx::query_as!(
+        Job,
+        r#"update jobs
+            set status = 'running', attempts = attempts + 1, updated_at = now()
+            where id = (select id
+                        from jobs
+                        where status = 'pending'
+                          and run_at <= now()
+                        order by run_at, id
+                        for update skip locked
+                        limit 1)
+            returning id, match_id, kind, attempts, seen_size, created_at"#,
+    )
+    .fetch_optional(executor)
+    .await?)
+}
+
+pub async fn complete_job(executor: impl PgExecutor<'_>, job_id: i32) -> Result<(), Error> {
+    sqlx::query!(
+        "update jobs set status = 'done', last_error = null, updated_at = now() where id = $1",
+        job_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn retry_job(
+    executor: impl PgExecutor<'_>,
+    job_id: i32,
+    error: &str,
+    run_at: OffsetDateTime,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"update jobs set status = 'pending', last_error = $2, run_at = $3, updated_at = now()
+            where id = $1"#,
+        job_id,
+        error,
+        run_at,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Runs a polling job again at `run_at`, a poll is not counted as an attempt
+pub async fn poll_job(
+    executor: impl PgExecutor<'_>,
+    job_id: i32,
+    seen_size: Option<i64>,
+    run_at: OffsetDateTime,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"update jobs
+            set status = 'pending', attempts = attempts - 1, seen_size = $2, run_at = $3,
+                updated_at = now()
+            where id = $1"#,
+        job_id,
+        seen_size,
+        run_at,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn fail_job(
+    executor: impl PgExecutor<'_>,
+    job_id: i32,
+    error: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update jobs set status = 'failed', last_error = $2, updated_at = now() where id = $1",
+        job_id,
+        error,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Jobs left running by a previous process never finished, so they are picked up again.
+pub async fn requeue_running_jobs(executor: impl PgExecutor<'_>) -> Result<u64, Error> {
+    Ok(sqlx::query!(
+        "update jobs set status = 'pending', updated_at = now() where status = 'running'"
+    )
+    .execute(executor)
+    .await?
+    .rows_affected())
+}
+
+pub async fn get_registered_servers(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<RegisteredServer>, Error> {
+    Ok(sqlx::query_as!(
+        RegisteredServer,
+        "select server_id, name, ingest_token from servers order by id"
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+pub async fn register_server(
+    executor: impl PgExecutor<'_>,
+    server_id: &str,
+    name: Option<&str>,
+    ingest_token: &str,
+) -> Result<RegisteredServer, Error> {
+    Ok(sqlx::query_as!(
+        RegisteredServer,
+        r#"insert into servers (server_id, name, ingest_token)
+            values ($1, $2, $3)
+            on conflict (server_id) do update
+                set name = coalesce(excluded.name, servers.name)
+            returning server_id, name, ingest_token"#,
+        server_id,
+        name,
+        ingest_token,
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+pub async fn rotate_server_token(
+    executor: impl PgExecutor<'_>,
+    server_id: &str,
+    ingest_token: &str,
+) -> Result<RegisteredServer, Error> {
+    sqlx::query_as!(
+        RegisteredServer,
+        r#"update servers set ingest_token = $2, token_rotated_at = now()
+            where server_id = $1
+            returning server_id, name, ingest_token"#,
+        server_id,
+        ingest_token,
+    )
+    .fetch_optional(executor)
+    .await?
+    .ok_or(Error::NotFound)
+}
+
+/// Removes rounds of a server not linked to a match yet, from `from_round` onwards
+pub async fn delete_unlinked_rounds(
+    executor: impl PgExecutor<'_>,
+    server_id: &ServerId,
+    from_round: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "delete from rounds where server_id = $1 and match_id is null and round_number >= $2",
+        server_id.0,
+        from_round
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn create_round(
+    executor: impl PgExecutor<'_>,
+    server_id: &ServerId,
+    round: &CompletedRound,
+) -> Result<i32, Error> {
+    Ok(sqlx::query_scalar!(
+        r#"insert into rounds (server_id, round_number, winner, win_reason, ct_score, t_score, started_at, ended_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8) returning id"#,
+        server_id.0,
+        round.round_number,
+        round.winner,
+        round.win_reason,
+        round.ct_score,
+        round.t_score,
+        round.started_at,
+        round.ended_at,
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+pub async fn create_kill_events(
+    executor: impl PgExecutor<'_>,
+    round_id: i32,
+    kills: &[KillEvent],
+) -> Result<(), Error> {
+    let attackers: Vec<Option<i64>> = kills
+        .iter()
+        .map(|k| k.attacker_steam_id.map(|id| id as i64))
+        .collect();
+    let victims: Vec<Option<i64>> = kills
+        .iter()
+        .map(|k| k.victim_steam_id.map(|id| id as i64))
+        .collect();
+    let weapons: Vec<String> = kills.iter().map(|k| k.weapon.clone()).collect();
+    let headshots: Vec<bool> = kills.iter().map(|k| k.headshot).collect();
+    let killed_at: Vec<OffsetDateTime> = kills.iter().map(|k| k.killed_at).collect();
+    sqlx::query!(
+        r#"insert into kill_events (round_id, attacker_steam_id, victim_steam_id, weapon, headshot, killed_at)
+            select $1, * from unnest($2::bigint[], $3::bigint[], $4::varchar[], $5::bool[], $6::timestamptz[])"#,
+        round_id,
+        &attackers as _,
+        &victims as _,
+        &weapons,
+        &headshots,
+        &killed_at,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Links the rounds played on a server since its last match to `match_id`
+pub async fn link_rounds(
+    executor: impl PgExecutor<'_>,
+    server_id: &ServerId,
+    match_id: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update rounds set match_id = $1 where server_id = $2 and match_id is null",
+        match_id,
+        server_id.0
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_rounds(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<Vec<RoundDetails>, Error> {
+    Ok(sqlx::query!(
+        r#"select id,
+       round_number,
+       winner,
+       win_reason,
+       ct_score,
+       t_score,
+       started_at,
+       ended_at,
+       extract(epoch from ended_at - started_at)::float8 as duration_secs
+from rounds
+where match_id = $1
+order by round_number"#,
+        match_id
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| RoundDetails {
+        id: r.id,
+        round_number: r.round_number,
+        winner: r.winner,
+        win_reason: r.win_reason,
+        ct_score: r.ct_score,
+        t_score: r.t_score,
+        started_at: r.started_at,
+        ended_at: r.ended_at,
+        duration_secs: r.duration_secs,
+        kills: vec![],
+    })
+    .collect())
+}
+
+pub async fn get_kill_events(
+    executor: impl PgExecutor<'_>,
+    match_id: i32,
+) -> Result<Vec<KillDetails>, Error> {
+    Ok(sqlx::query_as!(
+        KillDetails,
+        r#"select k.round_id,
+       k.attacker_steam_id::text as attacker_steam_id,
+       k.victim_steam_id::text   as victim_steam_id,
+       k.weapon,
+       k.headshot,
+       k.killed_at
+from kill_events k
+         join rounds r on r.id = k.round_id
+where r.match_id = $1
+order by k.killed_at, k.id"#,
+        match_id
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+/// Stored commands, rows with an unknown role are skipped with a warning
+pub async fn get_chat_commands(executor: impl PgExecutor<'_>) -> Result<Vec<ChatCommand>, Error> {
+    Ok(sqlx::query!(
+        "select name, aliases, role, cooldown_seconds, lines from chat_commands order by name"
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .filter_map(|r| match r.role.parse() {
+        Ok(role) => Some(ChatCommand {
+            role,
+            name: r.name,
+            aliases: r.aliases,
+            cooldown_seconds: r.cooldown_seconds.max(0) as u32,
+            lines: r.lines,
+        }),
+        Err(e) => {
+            tracing::warn!("skipping stored chat command '{}': {}", r.name, e);
+            None
+        }
+    })
+    .collect())
+}
+
+pub async fn upsert_chat_command(
+    executor: impl PgExecutor<'_>,
+    command: &ChatCommand,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"insert into chat_commands (name, aliases, role, cooldown_seconds, lines)
+values ($1, $2, $3, $4, $5)
+on conflict (name) do update set aliases          = excluded.aliases,
+                                 role             = excluded.role,
+                                 cooldown_seconds = excluded.cooldown_seconds,
+                                 lines            = excluded.lines,
+                                 updated_at       = now()"#,
+        command.name,
+        &command.aliases,
+        command.role.as_str(),
+        command.cooldown_seconds as i32,
+        &command.lines
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_chat_command(executor: impl PgExecutor<'_>, name: &str) -> Result<(), Error> {
+    let result = sqlx::query!("delete from chat_commands where name = $1", name)
         .execute(executor)
         .await?;
+    if result.rows_affected() == 0 {
+        return Err(Error::NotFound);
     }
     Ok(())
 }
+
+pub async fn create_scheduled_match(
+    executor: impl PgExecutor<'_>,
+    dathost_match_id: &str,
+    connect: &str,
+    request: &CreateMatch,
+) -> Result<ScheduledMatch, Error> {
+    Ok(sqlx::query_as!(
+        ScheduledMatch,
+        r#"insert into scheduled_matches (dathost_match_id, server_id, map, team1_name, team2_name, connect, request)
+values ($1, $2, $3, $4, $5, $6, $7)
+returning id, dathost_match_id, server_id, map, team1_name, team2_name, connect, match_id, created_at"#,
+        dathost_match_id,
+        request.server_id,
+        request.map,
+        request.team1.name,
+        request.team2.name,
+        connect,
+        Json(request) as _,
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Captains of the match scheduled on a server that has not finished yet
+pub async fn get_match_captains(
+    executor: impl PgExecutor<'_>,
+    server_id: &ServerId,
+) -> Result<Vec<u64>, Error> {
+    Ok(sqlx::query_scalar!(
+        r#"select c.steam_id as "steam_id!"
+from (select request
+      from scheduled_matches
+      where server_id = $1 and match_id is null
+      order by created_at desc
+      limit 1) s,
+     lateral (values (s.request -> 'team1' ->> 'captain'),
+                     (s.request -> 'team2' ->> 'captain')) as c(steam_id)
+where c.steam_id is not null"#,
+        server_id.0
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .filter_map(|steam_id| steam_id.parse().ok())
+    .collect())
+}
+
+/// Links the match created from a webhook to the match we scheduled, if any
+pub async fn link_scheduled_match(
+    executor: impl PgExecutor<'_>,
+    dathost_match_id: &DathostMatchId,
+    match_id: i32,
+) -> Result<(), Error> {
+    sqlx::query!(
+        "update scheduled_matches set match_id = $2 where dathost_match_id = $1",
+        dathost_match_id.0,
+        match_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_server(executor: impl PgExecutor<'_>, server_id: &str) -> Result<(), Error> {
+    sqlx::query!("delete from servers where server_id = $1", server_id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MatchStats, Player, ServerId, Settings, Team};
+    use sqlx::PgPool;
+
+    fn match_end(team: &str) -> DathostMatchEnd {
+        DathostMatchEnd {
+            id: DathostMatchId("65f1c0ffee".to_string()),
+            server_id: ServerId("65f1beef".to_string()),
+            team1: Team {
+                name: "Blue".to_string(),
+                stats: MatchStats { score: 13 },
+            },
+            team2: Team {
+                name: "Orange".to_string(),
+                stats: MatchStats { score: 7 },
+            },
+            players: vec![Player {
+                match_id: "65f1c0ffee".to_string(),
+                steam_id_64: 76561198000000001,
+                team: team.to_string(),
+                connected: true,
+                kicked: false,
+                stats: PlayerStats::default(),
+            }],
+            settings: Settings {
+                map: "de_mirage".to_string(),
+                connect_time: 300,
+                match_begin_countdown: 10,
+            },
+            rounds_played: 20,
+            finished: true,
+            cancel_reason: None,
+        }
+    }
+
+    async fn count(pool: &PgPool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("select count(*) from {table}"))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn failed_stats_insert_leaves_nothing_behind(pool: PgPool) {
+        // match_stats.team is a VARCHAR(10), so this team makes the stats insert fail
+        let dathost_match = match_end("team_name_too_long");
+        let mut tx = pool.begin().await.unwrap();
+        assert!(record_match(&mut tx, &dathost_match).await.is_err());
+        drop(tx);
+
+        assert_eq!(count(&pool, "matches").await, 0);
+        assert_eq!(count(&pool, "match_stats").await, 0);
+    }
+
+    #[sqlx::test]
+    async fn recorded_match_is_only_created_once(pool: PgPool) {
+        let dathost_match = match_end("team1");
+        let mut tx = pool.begin().await.unwrap();
+        let created = record_match(&mut tx, &dathost_match).await.unwrap();
+        tx.commit().await.unwrap();
+        assert!(created.is_some());
+
+        let mut tx = pool.begin().await.unwrap();
+        assert!(record_match(&mut tx, &dathost_match)
+            .await
+            .unwrap()
+            .is_none());
+        tx.commit().await.unwrap();
+
+        assert_eq!(count(&pool, "matches").await, 1);
+        assert_eq!(count(&pool, "match_stats").await, 1);
+    }
+
+    #[sqlx::test]
+    async fn polling_a_job_does_not_use_up_its_attempts(pool: PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let created = record_match(&mut tx, &match_end("team1")).await.unwrap();
+        tx.commit().await.unwrap();
+        let match_id = created.unwrap().id;
+        let now = OffsetDateTime::now_utc();
+        enqueue_job(&pool, match_id, JobKind::FetchDemo, now)
+            .await
+            .unwrap();
+
+        for seen_size in [None, Some(1024)] {
+            let job = claim_next_job(&pool).await.unwrap().unwrap();
+            assert_eq!(job.attempts, 1);
+            assert_eq!(job.seen_size, seen_size);
+            poll_job(&pool, job.id, Some(1024), now).await.unwrap();
+        }
+        let job = claim_next_job(&pool).await.unwrap().unwrap();
+        assert_eq!(job.attempts, 1);
+        assert_eq!(job.seen_size, Some(1024));
+    }
+}
@@ -0,0 +1,369 @@
+use std::str;
+
+const HL2_MAGIC: &[u8; 8] = b"HL2DEMO\0";
+const CS2_MAGIC: &[u8; 8] = b"PBDEMS2\0";
+/// The HL2DEMO header is a fixed 1072 bytes, the CS2 file header message is much smaller
+const HEAD_LEN: usize = 4096;
+/// The CS2 file info message is a few hundred bytes, anything past this is not needed
+const TAIL_LEN: usize = 64 * 1024;
+const HL2_STRING_LEN: usize = 260;
+const DEM_FILE_HEADER: u64 = 1;
+const DEM_FILE_INFO: u64 = 2;
+const DEM_IS_COMPRESSED: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemoFormat {
+    /// CS:GO and other Source 1 games
+    Hl2Demo,
+    /// CS2, a stream of protobuf messages
+    Pbdems2,
+}
+
+impl DemoFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DemoFormat::Hl2Demo => "HL2DEMO",
+            DemoFormat::Pbdems2 => "PBDEMS2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemoHeader {
+    pub format: DemoFormat,
+    pub map_name: Option<String>,
+    pub server_name: Option<String>,
+    pub playback_ticks: Option<i32>,
+    pub playback_seconds: Option<f32>,
+}
+
+/// Watches a demo go by chunk by chunk, keeping only the parts the header is read from.
+/// CS2 demos keep their playback length in a file info message near the end of the file,
+/// at an offset given in the first bytes.
+#[derive(Debug, Default)]
+pub struct DemoInspector {
+    head: Vec<u8>,
+    tail: Vec<u8>,
+    tail_start: Option<u64>,
+    position: u64,
+}
+
+impl DemoInspector {
+    /// Feeds the next chunk, returns false once the file is known not to be a demo
+    pub fn feed(&mut self, chunk: &[u8]) -> bool {
+        let start = self.position;
+        self.position += chunk.len() as u64;
+        if self.head.len() < HEAD_LEN {
+            let take = (HEAD_LEN - self.head.len()).min(chunk.len());
+            self.head.extend_from_slice(&chunk[..take]);
+        }
+        if self.head.len() < HL2_MAGIC.len() {
+            return true;
+        }
+        let Some(format) = self.format() else {
+            return false;
+        };
+        if format == DemoFormat::Pbdems2 && self.tail_start.is_none() && self.head.len() >= 12 {
+            self.tail_start = Some(read_u32(&self.head, 8) as u64);
+        }
+        if let Some(tail_start) = self.tail_start {
+            let end = self.position;
+            if end > tail_start && self.tail.len() < TAIL_LEN {
+                let from = tail_start.saturating_sub(start) as usize;
+                let take = (chunk.len() - from).min(TAIL_LEN - self.tail.len());
+                self.tail.extend_from_slice(&chunk[from..from + take]);
+            }
+        }
+        true
+    }
+
+    pub fn format(&self) -> Option<DemoFormat> {
+        match self.head.get(..8)? {
+            magic if magic == HL2_MAGIC => Some(DemoFormat::Hl2Demo),
+            magic if magic == CS2_MAGIC => Some(DemoFormat::Pbdems2),
+            _ => None,
+        }
+    }
+
+    /// Reads the header once the whole file has been fed, `None` if it is not a demo
+    pub fn header(&self) -> Option<DemoHeader> {
+        match self.format()? {
+            DemoFormat::Hl2Demo => parse_hl2_header(&self.head),
+            DemoFormat::Pbdems2 => Some(parse_cs2_header(&self.head, &self.tail)),
+        }
+    }
+}
+
+/// The Source 1 header is a fixed layout: magic, two protocol versions, four 260 byte
+/// strings (server, client, map, game directory) and then the playback length
+fn parse_hl2_header(head: &[u8]) -> Option<DemoHeader> {
+    let strings = 16;
+    let playback = strings + 4 * HL2_STRING_LEN;
+    if head.len() < playback + 8 {
+        return None;
+    }
+    let string = |i: usize| {
+        let start = strings + i * HL2_STRING_LEN;
+        fixed_string(&head[start..start + HL2_STRING_LEN])
+    };
+    Some(DemoHeader {
+        format: DemoFormat::Hl2Demo,
+        server_name: string(0),
+        map_name: string(2),
+        playback_seconds: Some(f32::from_le_bytes(
+            head[playback..playback + 4].try_into().ok()?,
+        )),
+        playback_ticks: Some(read_u32(head, playback + 4) as i32),
+    })
+}
+
+/// CS2 demos start with the magic and two offsets, followed by `DEM_FileHeader`.
+/// Fields that can not be read, e.g. from compressed messages, are left empty.
+fn parse_cs2_header(head: &[u8], tail: &[u8]) -> DemoHeader {
+    let mut header = DemoHeader {
+        format: DemoFormat::Pbdems2,
+        map_name: None,
+        server_name: None,
+        playback_ticks: None,
+        playback_seconds: None,
+    };
+    if let Some(message) = head
+        .get(16..)
+        .and_then(|m| read_message(m, DEM_FILE_HEADER))
+    {
+        for (field, value) in ProtoFields(message) {
+            match (field, value) {
+                (3, ProtoValue::Bytes(v)) => header.server_name = proto_string(v),
+                (5, ProtoValue::Bytes(v)) => header.map_name = proto_string(v),
+                _ => {}
+            }
+        }
+    }
+    if let Some(message) = read_message(tail, DEM_FILE_INFO) {
+        for (field, value) in ProtoFields(message) {
+            match (field, value) {
+                (1, ProtoValue::Fixed32(v)) => header.playback_seconds = Some(f32::from_bits(v)),
+                (2, ProtoValue::Varint(v)) => header.playback_ticks = Some(v as i32),
+                _ => {}
+            }
+        }
+    }
+    header
+}
+
+/// Reads a demo command (type, tick, size, payload) and returns its payload if it is `kind`
+fn read_message(data: &[u8], kind: u64) -> Option<&[u8]> {
+    let mut pos = 0;
+    let cmd = read_varint(data, &mut pos)?;
+    let _tick = read_varint(data, &mut pos)?;
+    let size = read_varint(data, &mut pos)? as usize;
+    if cmd & DEM_IS_COMPRESSED != 0 || cmd != kind {
+        return None;
+    }
+    data.get(pos..pos.checked_add(size)?)
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64,
+    Bytes(&'a [u8]),
+}
+
+/// Iterates the top level fields of a protobuf message, stopping at the first malformed one
+struct ProtoFields<'a>(&'a [u8]);
+
+impl<'a> Iterator for ProtoFields<'a> {
+    type Item = (u64, ProtoValue<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.0;
+        let mut pos = 0;
+        let key = read_varint(data, &mut pos)?;
+        let value = match key & 7 {
+            0 => ProtoValue::Varint(read_varint(data, &mut pos)?),
+            1 => {
+                pos += 8;
+                ProtoValue::Fixed64
+            }
+            2 => {
+                let len = read_varint(data, &mut pos)? as usize;
+                let bytes = data.get(pos..pos.checked_add(len)?)?;
+                pos += len;
+                ProtoValue::Bytes(bytes)
+            }
+            5 => {
+                let value = read_u32(data.get(pos..pos + 4)?, 0);
+                pos += 4;
+                ProtoValue::Fixed32(value)
+            }
+            _ => return None,
+        };
+        self.0 = data.get(pos..)?;
+        Some((key >> 3, value))
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn fixed_string(bytes: &[u8]) -> Option<String> {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    proto_string(&bytes[..end])
+}
+
+fn proto_string(bytes: &[u8]) -> Option<String> {
+    str::from_utf8(bytes)
+        .ok()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HL2_DEMO: &[u8] = include_bytes!("../tests/fixtures/hl2demo_header.dem");
+    const CS2_DEMO: &[u8] = include_bytes!("../tests/fixtures/pbdems2_header.dem");
+
+    /// Feeds `data` in chunks of `chunk_size`, the way it arrives from the server
+    fn inspect(data: &[u8], chunk_size: usize) -> (bool, DemoInspector) {
+        let mut inspector = DemoInspector::default();
+        let valid = data.chunks(chunk_size).all(|chunk| inspector.feed(chunk));
+        (valid, inspector)
+    }
+
+    /// A CS2 demo whose file header message is `message`, without a file info message
+    fn cs2_with_header(message: &[u8]) -> Vec<u8> {
+        let mut data = CS2_MAGIC.to_vec();
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(message);
+        data
+    }
+
+    #[test]
+    fn reads_hl2_header() {
+        for chunk_size in [1, 100, HL2_DEMO.len()] {
+            let (valid, inspector) = inspect(HL2_DEMO, chunk_size);
+            assert!(valid);
+            assert_eq!(
+                inspector.header(),
+                Some(DemoHeader {
+                    format: DemoFormat::Hl2Demo,
+                    map_name: Some("de_mirage".to_string()),
+                    server_name: Some("Scrimbot #1".to_string()),
+                    playback_ticks: Some(144416),
+                    playback_seconds: Some(2256.5),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn reads_cs2_header_and_file_info() {
+        // chunk sizes that split the file info message and its offset
+        for chunk_size in [1, 7, 64, CS2_DEMO.len()] {
+            let (valid, inspector) = inspect(CS2_DEMO, chunk_size);
+            assert!(valid);
+            assert_eq!(
+                inspector.header(),
+                Some(DemoHeader {
+                    format: DemoFormat::Pbdems2,
+                    map_name: Some("de_ancient".to_string()),
+                    server_name: Some("Scrimbot #1".to_string()),
+                    playback_ticks: Some(154384),
+                    playback_seconds: Some(2412.25),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn html_is_not_a_demo() {
+        let html = b"<!DOCTYPE html><html><body>502 Bad Gateway</body></html>";
+        let (valid, inspector) = inspect(html, 16);
+        assert!(!valid);
+        assert_eq!(inspector.format(), None);
+        assert_eq!(inspector.header(), None);
+    }
+
+    #[test]
+    fn truncated_headers() {
+        let (valid, inspector) = inspect(&HL2_DEMO[..4], 4);
+        assert!(valid);
+        assert_eq!(inspector.format(), None);
+
+        let (valid, inspector) = inspect(&HL2_DEMO[..500], 64);
+        assert!(valid);
+        assert_eq!(inspector.format(), Some(DemoFormat::Hl2Demo));
+        assert_eq!(inspector.header(), None);
+
+        // cut inside the file header message, before the file info offset is reached
+        for len in [10, 40] {
+            let (valid, inspector) = inspect(&CS2_DEMO[..len], 3);
+            assert!(valid);
+            let header = inspector.header().unwrap();
+            assert_eq!(header.format, DemoFormat::Pbdems2);
+            assert_eq!(header.map_name, None);
+            assert_eq!(header.playback_ticks, None);
+        }
+    }
+
+    #[test]
+    fn compressed_header_is_left_empty() {
+        let mut data = CS2_DEMO.to_vec();
+        data[16] |= DEM_IS_COMPRESSED as u8;
+        let (valid, inspector) = inspect(&data, 64);
+        assert!(valid);
+        let header = inspector.header().unwrap();
+        assert_eq!(header.map_name, None);
+        assert_eq!(header.server_name, None);
+        // the file info message is still read
+        assert_eq!(header.playback_ticks, Some(154384));
+    }
+
+    #[test]
+    fn oversized_varints_are_rejected() {
+        // a size varint that never terminates
+        let mut message = vec![DEM_FILE_HEADER as u8, 0];
+        message.extend_from_slice(&[0xff; 12]);
+        let (_, inspector) = inspect(&cs2_with_header(&message), 5);
+        assert_eq!(inspector.header().unwrap().map_name, None);
+
+        // a size far past the end of the data
+        let mut message = vec![DEM_FILE_HEADER as u8, 0];
+        message.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f, 0x2a]);
+        let (_, inspector) = inspect(&cs2_with_header(&message), 5);
+        assert_eq!(inspector.header().unwrap().map_name, None);
+
+        // a field length past the end of the message
+        let message = [
+            DEM_FILE_HEADER as u8,
+            0,
+            4,
+            5 << 3 | 2,
+            0xff,
+            0xff,
+            0x03,
+            b'x',
+        ];
+        let (_, inspector) = inspect(&cs2_with_header(&message), 5);
+        assert_eq!(inspector.header().unwrap().map_name, None);
+    }
+}
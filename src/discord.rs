@@ -1,5 +1,6 @@
-use crate::models::ActionRow;
+use crate::models::{ActionRow, Embed};
 use axum::http;
+use reqwest::multipart::{Form, Part};
 use reqwest::{Client, Response, Result};
 use serde_json::json;
 use std::env;
@@ -27,17 +28,51 @@ impl DiscordClient {
         Ok(Self(client))
     }
 
-    pub async fn send_msg(&self, content: &String, components: Vec<ActionRow>) -> Result<Response> {
+    pub async fn send_msg(
+        &self,
+        content: &String,
+        embeds: Vec<Embed>,
+        components: Vec<ActionRow>,
+    ) -> Result<Response> {
         let channel_id = env::var("DISCORD_CHANNEL_ID").expect("DISCORD_CHANNEL_ID must be set");
-        let body = json!({ "content": content, "components": components });
-        Ok(self
-            .0
+        let body = json!({ "content": content, "embeds": embeds, "components": components });
+        self.0
             .post(format!(
                 "{}/channels/{}/messages",
                 DISCORD_BASE_URL, channel_id
             ))
             .json(&body)
             .send()
-            .await?)
+            .await
+    }
+
+    /// Sends a message with a file attached, embeds can show it as `attachment://<file_name>`
+    pub async fn send_msg_with_file(
+        &self,
+        content: &String,
+        embeds: Vec<Embed>,
+        components: Vec<ActionRow>,
+        file_name: &str,
+        file: Vec<u8>,
+    ) -> Result<Response> {
+        let channel_id = env::var("DISCORD_CHANNEL_ID").expect("DISCORD_CHANNEL_ID must be set");
+        let body = json!({
+            "content": content,
+            "embeds": embeds,
+            "components": components,
+            "attachments": [{ "id": 0, "filename": file_name }],
+        });
+        let form = Form::new().text("payload_json", body.to_string()).part(
+            "files[0]",
+            Part::bytes(file).file_name(file_name.to_string()),
+        );
+        self.0
+            .post(format!(
+                "{}/channels/{}/messages",
+                DISCORD_BASE_URL, channel_id
+            ))
+            .multipart(form)
+            .send()
+            .await
     }
 }
@@ -10,10 +10,46 @@ pub enum Error {
     Reqwest(#[from] reqwest::Error),
     #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Png(#[from] png::EncodingError),
+    #[error("demo '{0}' was not ready on the server before the deadline")]
+    DemoTimeout(String),
+    #[error("demo '{0}' is not on the server")]
+    DemoMissing(String),
+    #[error("demo '{0}' download was incomplete")]
+    DemoIncomplete(String),
+    #[error("'{0}' is not a demo")]
+    InvalidDemo(String),
     #[error("failed to upload demo to S3")]
-    DemoUploadError,
+    DemoUpload,
+    #[error("stored demo '{0}' does not match the upload")]
+    DemoMismatch(String),
     #[error("failed to stop dathost server")]
-    StopServerError,
+    StopServer,
+    #[error("dathost responded with {0}")]
+    Dathost(reqwest::StatusCode),
+    #[error("failed to post end of match message to discord")]
+    SummaryPost,
+    #[error("not found")]
+    NotFound,
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("PUBLIC_URL is not set")]
+    PublicUrlMissing,
+}
+
+impl Error {
+    /// Errors that retrying the job won't fix
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            Error::DemoTimeout(_) | Error::DemoMissing(_) | Error::InvalidDemo(_)
+        )
+    }
 }
 
 impl IntoResponse for Error {
@@ -21,6 +57,12 @@ impl IntoResponse for Error {
         let json = json!({
             "error": self.to_string(),
         });
-        (StatusCode::INTERNAL_SERVER_ERROR, json.to_string()).into_response()
+        let status = match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Error::Dathost(StatusCode::NOT_FOUND) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, json.to_string()).into_response()
     }
 }
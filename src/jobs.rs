@@ -0,0 +1,748 @@
+use crate::db::{
+    claim_next_job, complete_job, create_demo, create_invalid_demo, enqueue_job, fail_job,
+    get_demo, get_match, get_match_payload, get_rounds, poll_job, requeue_running_jobs, retry_job,
+    set_match_demo_url, set_match_server_stopped, set_match_summary_posted,
+};
+use crate::demo::DemoInspector;
+use crate::errors::Error;
+use crate::models::{
+    ActionRow, Checksum, DathostMatch, DathostMatchEnd, Demo, EmbedImage, Job, JobKind,
+    MessageComponent, ServerId, SteamUser,
+};
+use crate::scoreboard::{render_scoreboard, SCOREBOARD_FILE_NAME};
+use crate::utils::{end_of_match_embed, end_of_match_msg, env_or, presign_demo, MAX_PRESIGN_SECS};
+use crate::AppState;
+use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+use futures::StreamExt;
+use s3::Bucket;
+use sha2::{Digest, Sha256};
+use sqlx::types::time::OffsetDateTime;
+use std::env;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::sleep;
+use tokio_util::io::StreamReader;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BASE_BACKOFF_SECS: u64 = 30;
+const MAX_BACKOFF_SECS: u64 = 30 * 60;
+const MB: u64 = 1024 * 1024;
+const PROGRESS_STEP: u64 = 50 * MB;
+/// S3 needs every part of a multipart upload but the last to be at least 5 MB
+const PART_SIZE: u64 = 8 * MB;
+const DEMO_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Runs queued post-match jobs forever, one at a time.
+pub async fn run_worker(state: AppState) {
+    match requeue_running_jobs(&state.db).await {
+        Ok(0) => {}
+        Ok(n) => tracing::info!("requeued {} interrupted jobs", n),
+        Err(e) => tracing::error!("unable to requeue interrupted jobs: {}", e),
+    }
+    loop {
+        match claim_next_job(&state.db).await {
+            Ok(Some(job)) => process_job(&state, job).await,
+            Ok(None) => sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                tracing::error!("unable to claim job: {}", e);
+                sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn process_job(state: &AppState, job: Job) {
+    tracing::info!(
+        "running job {} '{}' for match {} (attempt {})",
+        job.id,
+        job.kind,
+        job.match_id,
+        job.attempts
+    );
+    let kind = match job.kind.parse::<JobKind>() {
+        Ok(kind) => kind,
+        Err(e) => {
+            if let Err(e) = fail_job(&state.db, job.id, &e).await {
+                tracing::error!("unable to fail job {}: {}", job.id, e);
+            }
+            return;
+        }
+    };
+    let update = match run_job(state, &job, kind).await {
+        Ok(Outcome::Done(next)) => complete_and_enqueue(state, &job, next).await,
+        Ok(Outcome::Poll { seen_size, run_at }) => {
+            poll_job(&state.db, job.id, seen_size, run_at).await
+        }
+        Err(e) => {
+            tracing::error!("job {} '{}' failed: {}", job.id, job.kind, e);
+            if e.is_permanent() || job.attempts >= max_attempts() {
+                fail_and_continue(state, &job, kind, &e).await
+            } else {
+                let run_at = OffsetDateTime::now_utc() + backoff(job.attempts);
+                retry_job(&state.db, job.id, &e.to_string(), run_at).await
+            }
+        }
+    };
+    if let Err(e) = update {
+        tracing::error!("unable to update job {}: {}", job.id, e);
+    }
+}
+
+/// Completes a job and enqueues the one that follows it together, so a crash in between
+/// never leaves a match without a pending job
+async fn complete_and_enqueue(
+    state: &AppState,
+    job: &Job,
+    next: Option<JobKind>,
+) -> Result<(), Error> {
+    let mut tx = state.db.begin().await?;
+    complete_job(&mut *tx, job.id).await?;
+    if let Some(next) = next {
+        enqueue_job(&mut *tx, job.match_id, next, OffsetDateTime::now_utc()).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Fails a job for good. The match moves on past a failed demo job, so the server is
+/// still stopped and the summary still posted without the demo.
+async fn fail_and_continue(
+    state: &AppState,
+    job: &Job,
+    kind: JobKind,
+    error: &Error,
+) -> Result<(), Error> {
+    let mut tx = state.db.begin().await?;
+    fail_job(&mut *tx, job.id, &error.to_string()).await?;
+    if kind.is_optional() {
+        let cancelled = get_match(&mut *tx, job.match_id)
+            .await?
+            .cancel_reason
+            .is_some();
+        if let Some(next) = kind.next(cancelled) {
+            tracing::info!("continuing match {} with '{}'", job.match_id, next.as_str());
+            enqueue_job(&mut *tx, job.match_id, next, OffsetDateTime::now_utc()).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+enum Outcome {
+    /// The job is done, queueing the one that follows it
+    Done(Option<JobKind>),
+    /// The job is waiting on something and runs again at `run_at`
+    Poll {
+        seen_size: Option<i64>,
+        run_at: OffsetDateTime,
+    },
+}
+
+async fn run_job(state: &AppState, job: &Job, kind: JobKind) -> Result<Outcome, Error> {
+    let dathost_match = get_match(&state.db, job.match_id).await?;
+    match kind {
+        JobKind::StopServer => stop_server(state, &dathost_match).await?,
+        JobKind::FetchDemo => {
+            if let Some(poll) = fetch_demo(state, &dathost_match, job).await? {
+                return Ok(poll);
+            }
+        }
+        JobKind::UploadDemo => upload_demo(state, &dathost_match).await?,
+        JobKind::PostSummary => post_summary(state, &dathost_match).await?,
+    }
+    Ok(Outcome::Done(
+        kind.next(dathost_match.cancel_reason.is_some()),
+    ))
+}
+
+async fn stop_server(state: &AppState, dathost_match: &DathostMatch) -> Result<(), Error> {
+    if dathost_match.server_stopped_at.is_some() {
+        return Ok(());
+    }
+    let server_id = server_id(dathost_match)?;
+    tracing::info!("stopping server {}", server_id);
+    let stop_status = state.dathost.stop_server(&server_id).await?;
+    if stop_status.as_u16() != 200 {
+        tracing::error!("error stopping server {}", stop_status);
+        return Err(Error::StopServer);
+    }
+    set_match_server_stopped(&state.db, dathost_match.id).await
+}
+
+/// Checks whether the demo exists on the server and its size has stopped changing since
+/// the last poll, it is uploaded once the server is stopped. Until then the job is polled
+/// again every `DEMO_POLL_SECONDS`, for at most `DEMO_WAIT_SECONDS`.
+async fn fetch_demo(
+    state: &AppState,
+    dathost_match: &DathostMatch,
+    job: &Job,
+) -> Result<Option<Outcome>, Error> {
+    if get_demo(&state.db, dathost_match.id).await?.is_some() {
+        return Ok(None);
+    }
+    let server_id = server_id(dathost_match)?;
+    let path = demo_path(dathost_match)?;
+    let size = demo_size(state, &server_id, &path)
+        .await?
+        .map(|size| size as i64);
+    match size {
+        Some(size) if job.seen_size == Some(size) => {
+            tracing::info!("demo '{}' is ready ({} bytes)", path, size);
+            return Ok(None);
+        }
+        Some(size) => tracing::info!(
+            "demo '{}' is {} bytes, waiting for it to settle",
+            path,
+            size
+        ),
+        None => tracing::info!("demo '{}' not on the server yet", path),
+    }
+    let run_at = OffsetDateTime::now_utc() + Duration::from_secs(env_or("DEMO_POLL_SECONDS", 15));
+    let deadline = job.created_at + Duration::from_secs(env_or("DEMO_WAIT_SECONDS", 600));
+    if run_at > deadline {
+        return Err(Error::DemoTimeout(path));
+    }
+    Ok(Some(Outcome::Poll {
+        seen_size: size,
+        run_at,
+    }))
+}
+
+/// Streams the demo from the server into a multipart S3 upload without buffering it,
+/// compressing it on the way when `DEMO_COMPRESSION` is `zstd` or `gzip`
+async fn upload_demo(state: &AppState, dathost_match: &DathostMatch) -> Result<(), Error> {
+    if get_demo(&state.db, dathost_match.id).await?.is_some() {
+        return Ok(());
+    }
+    let server_id = server_id(dathost_match)?;
+    let path = demo_path(dathost_match)?;
+    let expected = demo_size(state, &server_id, &path)
+        .await?
+        .ok_or_else(|| Error::DemoMissing(path.clone()))?;
+    tracing::info!("streaming demo '{}' ({} MB) to s3", path, expected / MB);
+
+    let mut hasher = Sha256::new();
+    let mut inspector = DemoInspector::default();
+    let mut transferred = 0u64;
+    let mut next_progress = PROGRESS_STEP;
+    let stream = state
+        .dathost
+        .get_file_stream(&server_id, &path)
+        .await?
+        .map(|chunk| {
+            let chunk = chunk.map_err(io::Error::other)?;
+            // stops the upload before anything but a demo reaches the bucket
+            if !inspector.feed(&chunk) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "not a demo"));
+            }
+            hasher.update(&chunk);
+            transferred += chunk.len() as u64;
+            if transferred >= next_progress {
+                tracing::info!(
+                    "uploaded {} of {} MB of '{}'",
+                    transferred / MB,
+                    expected / MB,
+                    path
+                );
+                next_progress += PROGRESS_STEP;
+            }
+            Ok(chunk)
+        });
+    let reader = StreamReader::new(Box::pin(stream));
+    let (key, reader): (String, Box<dyn AsyncRead + Unpin + Send + '_>) =
+        match env::var("DEMO_COMPRESSION").as_deref() {
+            Ok("zstd") => (format!("{}.zst", path), Box::new(ZstdEncoder::new(reader))),
+            Ok("gzip") => (format!("{}.gz", path), Box::new(GzipEncoder::new(reader))),
+            Ok("none") | Err(_) => (path.clone(), Box::new(reader)),
+            Ok(other) => {
+                tracing::warn!(
+                    "unknown DEMO_COMPRESSION '{}', uploading uncompressed",
+                    other
+                );
+                (path.clone(), Box::new(reader))
+            }
+        };
+    // uploaded under a temporary key and only moved to `key` once it checks out, so a
+    // partial or corrupt upload never passes for the demo
+    let temp_key = format!("{}.part", key);
+    let uploaded = upload_object(&state.bucket, reader, &temp_key).await;
+    let stored = match (inspector.format(), uploaded) {
+        (None, _) => {
+            tracing::error!(
+                "'{}' on {} is not a demo, not uploading it",
+                path,
+                server_id
+            );
+            if let Err(e) = create_invalid_demo(&state.db, dathost_match.id).await {
+                tracing::error!(
+                    "unable to mark the demo of match {} invalid: {}",
+                    dathost_match.id,
+                    e
+                );
+            }
+            Err(Error::InvalidDemo(path.clone()))
+        }
+        (Some(_), Err(e)) => Err(e),
+        (Some(_), Ok(_)) if transferred != expected => {
+            tracing::error!(
+                "demo '{}' streamed {} bytes, expected {}",
+                path,
+                transferred,
+                expected
+            );
+            Err(Error::DemoIncomplete(path.clone()))
+        }
+        (Some(_), Ok(uploaded)) => move_object(&state.bucket, &temp_key, &key, &uploaded)
+            .await
+            .map(|_| uploaded.checksum),
+    };
+    let object = match stored {
+        Ok(object) => object,
+        Err(e) => {
+            delete_object(&state.bucket, &temp_key).await;
+            return Err(e);
+        }
+    };
+    let header = inspector.header();
+    match &header {
+        Some(header) => tracing::info!("demo header: {:?}", header),
+        None => tracing::warn!("unable to read the header of demo '{}'", path),
+    }
+    let demo = Checksum {
+        size: transferred,
+        sha256: hex::encode(hasher.finalize()),
+    };
+    tracing::info!(
+        "uploaded demo '{}' (sha256 {}) as '{}' (sha256 {})",
+        path,
+        demo.sha256,
+        key,
+        object.sha256
+    );
+    let mut tx = state.db.begin().await?;
+    create_demo(
+        &mut *tx,
+        dathost_match.id,
+        &key,
+        &demo,
+        &object,
+        header.as_ref(),
+    )
+    .await?;
+    if let Ok(bucket_base_url) = env::var("BUCKET_BASE_URL") {
+        let demo_url = format!("{}/{}", bucket_base_url, &key);
+        set_match_demo_url(&mut *tx, dathost_match.id, &demo_url).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// The bytes sent to the bucket and the ETag S3 gives them when stored intact
+struct UploadedObject {
+    checksum: Checksum,
+    etag: String,
+}
+
+/// Uploads `reader` in parts, checking each part's ETag against the MD5 of what was sent.
+/// A failed upload is aborted so its parts are not left behind in the bucket.
+async fn upload_object(
+    bucket: &Bucket,
+    mut reader: impl AsyncRead + Unpin,
+    key: &str,
+) -> Result<UploadedObject, Error> {
+    let upload = bucket
+        .initiate_multipart_upload(key, DEMO_CONTENT_TYPE)
+        .await?;
+    let uploaded = upload_parts(bucket, &mut reader, key, &upload.upload_id).await;
+    if uploaded.is_err() {
+        if let Err(e) = bucket.abort_upload(key, &upload.upload_id).await {
+            tracing::error!("unable to abort the upload of '{}': {}", key, e);
+        }
+    }
+    uploaded
+}
+
+async fn upload_parts(
+    bucket: &Bucket,
+    reader: &mut (impl AsyncRead + Unpin),
+    key: &str,
+    upload_id: &str,
+) -> Result<UploadedObject, Error> {
+    let mut parts = Vec::new();
+    let mut digests = Vec::new();
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    loop {
+        let mut chunk = Vec::with_capacity(PART_SIZE as usize);
+        (&mut *reader)
+            .take(PART_SIZE)
+            .read_to_end(&mut chunk)
+            .await?;
+        if chunk.is_empty() && !parts.is_empty() {
+            break;
+        }
+        let last = (chunk.len() as u64) < PART_SIZE;
+        size += chunk.len() as u64;
+        hasher.update(&chunk);
+        let digest = md5::compute(&chunk);
+        let part_number = parts.len() as u32 + 1;
+        let part = bucket
+            .put_multipart_chunk(chunk, key, part_number, upload_id, DEMO_CONTENT_TYPE)
+            .await?;
+        if part.etag.trim_matches('"') != format!("{:x}", digest) {
+            tracing::error!(
+                "part {} of '{}' stored with ETag {}, sent MD5 {:x}",
+                part_number,
+                key,
+                part.etag,
+                digest
+            );
+            return Err(Error::DemoMismatch(key.to_string()));
+        }
+        digests.push(digest);
+        parts.push(part);
+        if last {
+            break;
+        }
+    }
+    let response = bucket
+        .complete_multipart_upload(key, upload_id, parts)
+        .await?;
+    if response.status_code() != 200 {
+        tracing::error!("s3 error completing '{}': {}", key, response.status_code());
+        return Err(Error::DemoUpload);
+    }
+    Ok(UploadedObject {
+        checksum: Checksum {
+            size,
+            sha256: hex::encode(hasher.finalize()),
+        },
+        etag: multipart_etag(&digests),
+    })
+}
+
+/// The ETag S3 gives a multipart upload, the MD5 of its parts' MD5s and the part count
+fn multipart_etag(digests: &[md5::Digest]) -> String {
+    let concatenated: Vec<u8> = digests.iter().flat_map(|digest| digest.0).collect();
+    format!("{:x}-{}", md5::compute(concatenated), digests.len())
+}
+
+/// Moves the object at `from` to `to` if S3 reports the size and ETag of what was uploaded
+async fn move_object(
+    bucket: &Bucket,
+    from: &str,
+    to: &str,
+    uploaded: &UploadedObject,
+) -> Result<(), Error> {
+    let (head, s3_status) = bucket.head_object(from).await?;
+    let etag = head.e_tag.as_deref().map(|etag| etag.trim_matches('"'));
+    if s3_status != 200
+        || head.content_length != Some(uploaded.checksum.size as i64)
+        || etag != Some(uploaded.etag.as_str())
+    {
+        tracing::error!(
+            "stored '{}' is {:?} bytes with ETag {:?}, uploaded {} bytes with ETag {}",
+            from,
+            head.content_length,
+            etag,
+            uploaded.checksum.size,
+            uploaded.etag
+        );
+        return Err(Error::DemoMismatch(to.to_string()));
+    }
+    let s3_status = bucket.copy_object_internal(from, to).await?;
+    if s3_status != 200 {
+        tracing::error!("s3 error copying '{}' to '{}': {}", from, to, s3_status);
+        return Err(Error::DemoUpload);
+    }
+    delete_object(bucket, from).await;
+    Ok(())
+}
+
+/// Deletes an object that is no longer needed, a failure only leaves it behind
+async fn delete_object(bucket: &Bucket, key: &str) {
+    match bucket.delete_object(key).await {
+        Ok(response) if (200..300).contains(&response.status_code()) => {}
+        Ok(response) => tracing::error!(
+            "unable to delete '{}': s3 status {}",
+            key,
+            response.status_code()
+        ),
+        Err(e) => tracing::error!("unable to delete '{}': {}", key, e),
+    }
+}
+
+async fn post_summary(state: &AppState, dathost_match: &DathostMatch) -> Result<(), Error> {
+    if dathost_match.summary_posted_at.is_some() {
+        return Ok(());
+    }
+    let demo = get_demo(&state.db, dathost_match.id).await?;
+    let (eom, embeds, image) = match &dathost_match.cancel_reason {
+        Some(reason) => (
+            format!(
+                "Match cancelled: {}\n{} vs {} on {}",
+                reason, dathost_match.team1_name, dathost_match.team2_name, dathost_match.map
+            ),
+            vec![],
+            None,
+        ),
+        None => {
+            let payload = get_match_payload(&state.db, dathost_match.id).await?;
+            let steam_ids = payload.players.iter().map(|p| p.steam_id_64).collect();
+            let steam_users = state.steam.get_player_summaries(steam_ids).await?;
+            let image = scoreboard_image(&payload, &steam_users);
+            if summary_as_text() {
+                (end_of_match_msg(&payload, &steam_users), vec![], image)
+            } else {
+                let duration = match_duration(state, dathost_match, demo.as_ref()).await?;
+                let mut embed = end_of_match_embed(
+                    &payload,
+                    &steam_users,
+                    dathost_match.completed_at,
+                    duration,
+                );
+                if image.is_some() {
+                    embed.image = Some(EmbedImage {
+                        url: format!("attachment://{}", SCOREBOARD_FILE_NAME),
+                    });
+                }
+                (String::new(), vec![embed], image)
+            }
+        }
+    };
+    // a private bucket gets the longest lived presigned link instead of a public one
+    let demo_url = match (&dathost_match.demo_url, &demo) {
+        (Some(demo_url), _) => Some(demo_url.clone()),
+        (None, Some(demo)) => match demo.stored_key() {
+            Some(key) => Some(presign_demo(&state.bucket, key, MAX_PRESIGN_SECS)?),
+            None => None,
+        },
+        _ => None,
+    };
+    let components = match demo_url {
+        Some(demo_url) => vec![ActionRow {
+            component_type: 1,
+            components: vec![MessageComponent {
+                component_type: 2,
+                label: "Download Demo".to_string(),
+                style: 5,
+                custom_id: None,
+                url: Some(demo_url),
+            }],
+        }],
+        None => vec![],
+    };
+    tracing::info!("sending end of match message");
+    let discord_resp = match image {
+        Some(image) => {
+            state
+                .discord
+                .send_msg_with_file(&eom, embeds, components, SCOREBOARD_FILE_NAME, image)
+                .await?
+        }
+        None => state.discord.send_msg(&eom, embeds, components).await?,
+    };
+    if discord_resp.status() != 200 {
+        tracing::error!("discord error resp: {}", discord_resp.text().await?);
+        return Err(Error::SummaryPost);
+    }
+    set_match_summary_posted(&state.db, dathost_match.id).await
+}
+
+/// The size of the demo on the server, `None` until it has been written to
+async fn demo_size(
+    state: &AppState,
+    server_id: &ServerId,
+    path: &str,
+) -> Result<Option<u64>, Error> {
+    Ok(state
+        .dathost
+        .list_files(server_id, "")
+        .await?
+        .into_iter()
+        .find(|f| f.path.trim_start_matches('/') == path)
+        .and_then(|f| f.size)
+        .filter(|size| *size > 0))
+}
+
+fn server_id(dathost_match: &DathostMatch) -> Result<ServerId, Error> {
+    dathost_match
+        .server_id
+        .clone()
+        .map(ServerId)
+        .ok_or(Error::NotFound)
+}
+
+fn demo_path(dathost_match: &DathostMatch) -> Result<String, Error> {
+    dathost_match
+        .dathost_match_id
+        .as_ref()
+        .map(|id| format!("{}.dem", id))
+        .ok_or(Error::NotFound)
+}
+
+fn max_attempts() -> i32 {
+    env_or("JOB_MAX_ATTEMPTS", 5)
+}
+
+/// `DISCORD_SUMMARY_FORMAT=text` posts the scoreboard as a code block instead of an embed
+fn summary_as_text() -> bool {
+    env::var("DISCORD_SUMMARY_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("text"))
+}
+
+/// The rendered scoreboard, `DISCORD_SUMMARY_IMAGE=false` turns it off.
+/// A failed render is logged and the summary is posted without it.
+fn scoreboard_image(payload: &DathostMatchEnd, steam_users: &[SteamUser]) -> Option<Vec<u8>> {
+    if env::var("DISCORD_SUMMARY_IMAGE").is_ok_and(|v| v.eq_ignore_ascii_case("false")) {
+        return None;
+    }
+    render_scoreboard(payload, steam_users)
+        .map_err(|e| tracing::error!("failed to render scoreboard: {}", e))
+        .ok()
+}
+
+/// From the first ingested round to the last, or the demo length without ingest
+async fn match_duration(
+    state: &AppState,
+    dathost_match: &DathostMatch,
+    demo: Option<&Demo>,
+) -> Result<Option<Duration>, Error> {
+    let rounds = get_rounds(&state.db, dathost_match.id).await?;
+    let start = rounds
+        .iter()
+        .filter_map(|r| r.started_at)
+        .min()
+        .or_else(|| rounds.first().map(|r| r.ended_at));
+    let end = rounds.iter().map(|r| r.ended_at).max();
+    if let (Some(start), Some(end)) = (start, end) {
+        if end > start {
+            return Ok(Some((end - start).unsigned_abs()));
+        }
+    }
+    Ok(demo
+        .and_then(|d| d.playback_seconds)
+        .filter(|s| *s > 0.0)
+        .map(Duration::from_secs_f32))
+}
+
+fn backoff(attempts: i32) -> Duration {
+    let secs = BASE_BACKOFF_SECS.saturating_mul(1 << attempts.clamp(1, 16).saturating_sub(1));
+    Duration::from_secs(secs.min(MAX_BACKOFF_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use s3::creds::Credentials;
+    use s3::Region;
+
+    /// Larger than a multipart chunk, so the upload is split into parts
+    const LARGE: usize = 40 * MB as usize;
+
+    /// The bucket configured like the service's, with path style addressing for MinIO.
+    /// Run with `cargo test -- --ignored` and `AWS_ENDPOINT`, `BUCKET_NAME` and the
+    /// AWS credentials set.
+    fn test_bucket() -> Bucket {
+        let endpoint = env::var("AWS_ENDPOINT").expect("AWS_ENDPOINT must be set");
+        let region = env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let name = env::var("BUCKET_NAME").expect("BUCKET_NAME must be set");
+        Bucket::new(
+            &name,
+            Region::Custom { region, endpoint },
+            Credentials::default().unwrap(),
+        )
+        .unwrap()
+        .with_path_style()
+    }
+
+    /// Bytes that differ from part to part, so reordered or repeated parts change the hash
+    fn demo_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f491u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3 compatible server such as MinIO"]
+    async fn large_upload_is_moved_once_verified() {
+        let bucket = test_bucket();
+        let bytes = demo_bytes(LARGE);
+        let uploaded = upload_object(&bucket, &bytes[..], "test/large.dem.part")
+            .await
+            .unwrap();
+        assert_eq!(uploaded.checksum.size, LARGE as u64);
+        assert_eq!(
+            uploaded.checksum.sha256,
+            hex::encode(Sha256::digest(&bytes))
+        );
+        assert!(uploaded.etag.ends_with("-5"));
+
+        move_object(&bucket, "test/large.dem.part", "test/large.dem", &uploaded)
+            .await
+            .unwrap();
+        let (head, status) = bucket.head_object("test/large.dem").await.unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(head.content_length, Some(LARGE as i64));
+        let (_, status) = bucket.head_object("test/large.dem.part").await.unwrap();
+        assert_eq!(status, 404);
+        delete_object(&bucket, "test/large.dem").await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3 compatible server such as MinIO"]
+    async fn mismatched_upload_is_not_moved() {
+        let bucket = test_bucket();
+        let bytes = demo_bytes(1024);
+        let mut uploaded = upload_object(&bucket, &bytes[..], "test/mismatch.dem.part")
+            .await
+            .unwrap();
+        uploaded.etag = multipart_etag(&[md5::compute(b"something else")]);
+
+        let result = move_object(
+            &bucket,
+            "test/mismatch.dem.part",
+            "test/mismatch.dem",
+            &uploaded,
+        )
+        .await;
+        assert!(matches!(result, Err(Error::DemoMismatch(_))));
+        let (_, status) = bucket.head_object("test/mismatch.dem").await.unwrap();
+        assert_eq!(status, 404);
+        delete_object(&bucket, "test/mismatch.dem.part").await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs an S3 compatible server such as MinIO"]
+    async fn failed_upload_is_aborted() {
+        let bucket = test_bucket();
+        let chunks = vec![
+            Ok(bytes::Bytes::from(demo_bytes(LARGE / 4))),
+            Err(io::Error::other("server went away")),
+        ];
+        let reader = StreamReader::new(futures::stream::iter(chunks));
+
+        let result = upload_object(&bucket, reader, "test/aborted.dem.part").await;
+        assert!(matches!(result, Err(Error::Io(_))));
+        let uploads = bucket
+            .list_multiparts_uploads(Some("test/aborted"), None)
+            .await
+            .unwrap();
+        assert!(uploads.iter().all(|page| page.uploads.is_empty()));
+    }
+
+    #[test]
+    fn multipart_etag_hashes_the_part_digests() {
+        let digests = [md5::compute(b"a"), md5::compute(b"b")];
+        assert_eq!(
+            multipart_etag(&digests),
+            "96e024ba2074fe77e8e965ba43a704be-2"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::logs::Side;
+use crate::models::ServerId;
+use sqlx::types::time::OffsetDateTime;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// In-memory state of the matches currently being played, keyed by game server.
+/// Only what is needed between log batches lives here; anything worth keeping is
+/// written to the database.
+#[derive(Clone, Default)]
+pub struct LiveServers(Arc<Mutex<HashMap<ServerId, LiveServer>>>);
+
+impl LiveServers {
+    pub fn lock(&self) -> MutexGuard<'_, HashMap<ServerId, LiveServer>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn reset(&self, server_id: &ServerId) {
+        self.lock().remove(server_id);
+    }
+}
+
+#[derive(Default)]
+pub struct LiveServer {
+    /// Set once warmup is over and the match is live
+    pub started: bool,
+    pub ct_score: i32,
+    pub t_score: i32,
+    pub round_started_at: Option<OffsetDateTime>,
+    pub round_kills: Vec<KillEvent>,
+    pub pause: Option<Pause>,
+    pub pauses_started: u64,
+    /// Keyed by team, sides swap at halftime but the limit is per team
+    pub tactical_pauses_used: HashMap<MatchTeam, u32>,
+    /// Steam ids of the players that typed `!ready` during warmup
+    pub ready: HashSet<u64>,
+    pub ready_timer_started: bool,
+    /// When each command with a cooldown was last used
+    pub cooldowns: HashMap<String, Instant>,
+}
+
+impl LiveServer {
+    /// The team on `side` given the rounds played so far. Team one starts on CT and the
+    /// sides swap every half, at halftime, at the start of overtime and every overtime half.
+    pub fn team_on(&self, side: Side, max_rounds: i32, overtime_rounds: i32) -> Option<MatchTeam> {
+        let rounds = self.ct_score + self.t_score;
+        let halves = if rounds < max_rounds {
+            rounds / (max_rounds / 2).max(1)
+        } else {
+            2 + (rounds - max_rounds) / (overtime_rounds / 2).max(1)
+        };
+        match (side, halves % 2 == 1) {
+            (Side::CounterTerrorist, false) | (Side::Terrorist, true) => Some(MatchTeam::Team1),
+            (Side::Terrorist, false) | (Side::CounterTerrorist, true) => Some(MatchTeam::Team2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchTeam {
+    Team1,
+    Team2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseKind {
+    Technical,
+    Regular,
+    Tactical(Side),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pause {
+    /// Distinguishes pauses so a tactical timeout's timer never ends a later pause
+    pub id: u64,
+    pub kind: PauseKind,
+    /// Sides that have typed `!unpause`
+    pub unpause_requests: HashSet<Side>,
+}
+
+/// A round that has ended, ready to be saved
+#[derive(Debug, Clone)]
+pub struct CompletedRound {
+    pub round_number: i32,
+    pub winner: String,
+    pub win_reason: String,
+    pub ct_score: i32,
+    pub t_score: i32,
+    pub started_at: Option<OffsetDateTime>,
+    pub ended_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct KillEvent {
+    pub attacker_steam_id: Option<u64>,
+    pub victim_steam_id: Option<u64>,
+    pub weapon: String,
+    pub headshot: bool,
+    pub killed_at: OffsetDateTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn after(ct_score: i32, t_score: i32) -> LiveServer {
+        LiveServer {
+            ct_score,
+            t_score,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn teams_swap_sides_every_half() {
+        let ct = |server: LiveServer| server.team_on(Side::CounterTerrorist, 24, 6);
+        assert_eq!(ct(after(0, 0)), Some(MatchTeam::Team1));
+        assert_eq!(ct(after(7, 4)), Some(MatchTeam::Team1));
+        assert_eq!(ct(after(5, 7)), Some(MatchTeam::Team2));
+        assert_eq!(ct(after(11, 12)), Some(MatchTeam::Team2));
+        // overtime starts with a swap and swaps again every three rounds
+        assert_eq!(ct(after(12, 12)), Some(MatchTeam::Team1));
+        assert_eq!(ct(after(14, 12)), Some(MatchTeam::Team1));
+        assert_eq!(ct(after(14, 13)), Some(MatchTeam::Team2));
+        assert_eq!(ct(after(15, 15)), Some(MatchTeam::Team1));
+        assert_eq!(
+            after(5, 7).team_on(Side::Terrorist, 24, 6),
+            Some(MatchTeam::Team1)
+        );
+        assert_eq!(after(0, 0).team_on(Side::Spectator, 24, 6), None);
+    }
+}
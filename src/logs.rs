@@ -0,0 +1,630 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time};
+
+const STEAM_ID_64_BASE: u64 = 76561197960265728;
+
+// "Name<userid><steamid><team>", the team is left out of some lines
+const PLAYER: &str = r#""(.*?<-?\d+><[^>]*>(?:<[^>]*>)?)""#;
+const POSITION: &str = r"(?: \[[^\]]*\])?";
+
+static PREFIX_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:L )?(?<month>\d{2})/(?<day>\d{2})/(?<year>\d{4}) - (?<hour>\d{2}):(?<minute>\d{2}):(?<second>\d{2})(?:\.(?<millis>\d{1,3}))?(?::| -) (?<msg>.*)$",
+    )
+    .unwrap()
+});
+static PLAYER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(?<name>.*?)<(?<user_id>-?\d+)><(?<steam_id>[^>]*)>(?:<(?<team>[^>]*)>)?$")
+        .unwrap()
+});
+static KILL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER}{POSITION} killed {PLAYER}{POSITION} with "(?<weapon>[^"]*)"(?: \((?<flags>[^)]*)\))?"#
+    ))
+    .unwrap()
+});
+static ASSIST_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} (?<kind>assisted|flash-assisted) killing {PLAYER}"#
+    ))
+    .unwrap()
+});
+static DAMAGE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER}{POSITION} attacked {PLAYER}{POSITION} with "(?<weapon>[^"]*)" \(damage "(?<damage>\d+)"\) \(damage_armor "(?<damage_armor>\d+)"\) \(health "(?<health>\d+)"\) \(armor "\d+"\) \(hitgroup "(?<hitgroup>[^"]*)"\)"#
+    ))
+    .unwrap()
+});
+static ROUND_END_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^Team "(?<team>[^"]*)" triggered "SFUI_Notice_(?<reason>[^"]*)" \(CT "(?<ct>\d+)"\) \(T "(?<t>\d+)"\)"#,
+    )
+    .unwrap()
+});
+static BOMB_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} triggered "(?<action>Planted_The_Bomb|Defused_The_Bomb)"(?: at bombsite (?<site>\w+))?"#
+    ))
+    .unwrap()
+});
+static SWITCH_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} switched from team <(?<from>[^>]*)> to <(?<to>[^>]*)>"#
+    ))
+    .unwrap()
+});
+static CONNECT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} connected, address "(?<address>[^"]*)""#
+    ))
+    .unwrap()
+});
+static DISCONNECT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} disconnected(?: \(reason "(?<reason>[^"]*)"\))?"#
+    ))
+    .unwrap()
+});
+static CHAT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r#"^{PLAYER} (?<scope>say|say_team) "(?<message>.*)"$"#
+    ))
+    .unwrap()
+});
+static MATCH_START_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^World triggered "Match_Start" on "(?<map>[^"]*)""#).unwrap());
+static MATCH_STATUS_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"^MatchStatus: Score: (?<ct>\d+):(?<t>\d+) on map "(?<map>[^"]*)" RoundsPlayed: (?<rounds>-?\d+)"#,
+    )
+    .unwrap()
+});
+static GAME_OVER_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^Game Over: \w+ +(?:\S+ +)?(?<map>\S+) score (?<ct>\d+):(?<t>\d+) after (?<minutes>\d+) min",
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    CounterTerrorist,
+    Terrorist,
+    Spectator,
+    Unassigned,
+}
+
+impl Side {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Side::CounterTerrorist => "CT",
+            Side::Terrorist => "T",
+            Side::Spectator => "Spectator",
+            Side::Unassigned => "Unassigned",
+        }
+    }
+
+    pub fn opponent(&self) -> Option<Side> {
+        match self {
+            Side::CounterTerrorist => Some(Side::Terrorist),
+            Side::Terrorist => Some(Side::CounterTerrorist),
+            _ => None,
+        }
+    }
+
+    fn parse(team: &str) -> Self {
+        match team {
+            "CT" => Side::CounterTerrorist,
+            "TERRORIST" | "T" => Side::Terrorist,
+            "Spectator" => Side::Spectator,
+            _ => Side::Unassigned,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatScope {
+    All,
+    Team,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogPlayer {
+    pub name: String,
+    pub user_id: i32,
+    /// `None` for bots and the console
+    pub steam_id: Option<u64>,
+    pub side: Option<Side>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogEvent {
+    Kill {
+        attacker: LogPlayer,
+        victim: LogPlayer,
+        weapon: String,
+        headshot: bool,
+    },
+    Assist {
+        assister: LogPlayer,
+        victim: LogPlayer,
+        flash: bool,
+    },
+    Damage {
+        attacker: LogPlayer,
+        victim: LogPlayer,
+        weapon: String,
+        damage: i32,
+        damage_armor: i32,
+        health: i32,
+        hitgroup: String,
+    },
+    RoundStart,
+    RoundEnd {
+        winner: Side,
+        /// The round end notice without its `SFUI_Notice_` prefix, e.g. `Bomb_Defused`
+        reason: String,
+        ct_score: i32,
+        t_score: i32,
+    },
+    BombPlanted {
+        player: LogPlayer,
+        site: Option<String>,
+    },
+    BombDefused {
+        player: LogPlayer,
+    },
+    TeamSwitch {
+        player: LogPlayer,
+        from: Side,
+        to: Side,
+    },
+    Connected {
+        player: LogPlayer,
+        address: String,
+    },
+    Disconnected {
+        player: LogPlayer,
+        reason: Option<String>,
+    },
+    Chat {
+        player: LogPlayer,
+        message: String,
+        scope: ChatScope,
+    },
+    MatchStart {
+        map: String,
+    },
+    MatchStatus {
+        map: String,
+        ct_score: i32,
+        t_score: i32,
+        rounds_played: i32,
+    },
+    GameOver {
+        map: String,
+        ct_score: i32,
+        t_score: i32,
+        minutes: i32,
+    },
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    /// Server time of the line, servers are expected to log in UTC
+    pub timestamp: Option<OffsetDateTime>,
+    pub event: LogEvent,
+}
+
+/// Parses every non-empty line of an HTTP log batch
+pub fn parse_lines(body: &str) -> impl Iterator<Item = LogLine> + '_ {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+}
+
+pub fn parse_line(line: &str) -> LogLine {
+    let line = line.trim();
+    let Some(captures) = PREFIX_REGEX.captures(line) else {
+        return LogLine {
+            timestamp: None,
+            event: LogEvent::Unknown(line.to_string()),
+        };
+    };
+    let msg = captures.name("msg").map_or("", |m| m.as_str());
+    LogLine {
+        timestamp: parse_timestamp(&captures),
+        event: parse_event(msg).unwrap_or_else(|| LogEvent::Unknown(msg.to_string())),
+    }
+}
+
+fn parse_timestamp(captures: &Captures) -> Option<OffsetDateTime> {
+    let number = |name: &str| captures.name(name)?.as_str().parse::<u16>().ok();
+    let month = Month::try_from(number("month")? as u8).ok()?;
+    let date =
+        Date::from_calendar_date(number("year")? as i32, month, number("day")? as u8).ok()?;
+    let time = Time::from_hms_milli(
+        number("hour")? as u8,
+        number("minute")? as u8,
+        number("second")? as u8,
+        number("millis").unwrap_or(0),
+    )
+    .ok()?;
+    Some(PrimitiveDateTime::new(date, time).assume_utc())
+}
+
+fn parse_event(msg: &str) -> Option<LogEvent> {
+    if msg.starts_with(r#"World triggered "Round_Start""#) {
+        return Some(LogEvent::RoundStart);
+    }
+    if let Some(c) = KILL_REGEX.captures(msg) {
+        let flags = c.name("flags").map_or("", |m| m.as_str());
+        return Some(LogEvent::Kill {
+            attacker: parse_player(&c[1])?,
+            victim: parse_player(&c[2])?,
+            weapon: c["weapon"].to_string(),
+            headshot: flags.split_whitespace().any(|f| f == "headshot"),
+        });
+    }
+    if let Some(c) = DAMAGE_REGEX.captures(msg) {
+        return Some(LogEvent::Damage {
+            attacker: parse_player(&c[1])?,
+            victim: parse_player(&c[2])?,
+            weapon: c["weapon"].to_string(),
+            damage: c["damage"].parse().ok()?,
+            damage_armor: c["damage_armor"].parse().ok()?,
+            health: c["health"].parse().ok()?,
+            hitgroup: c["hitgroup"].to_string(),
+        });
+    }
+    if let Some(c) = ASSIST_REGEX.captures(msg) {
+        return Some(LogEvent::Assist {
+            assister: parse_player(&c[1])?,
+            victim: parse_player(&c[3])?,
+            flash: &c["kind"] == "flash-assisted",
+        });
+    }
+    if let Some(c) = CHAT_REGEX.captures(msg) {
+        return Some(LogEvent::Chat {
+            player: parse_player(&c[1])?,
+            message: c["message"].trim().to_string(),
+            scope: match &c["scope"] {
+                "say_team" => ChatScope::Team,
+                _ => ChatScope::All,
+            },
+        });
+    }
+    if let Some(c) = ROUND_END_REGEX.captures(msg) {
+        return Some(LogEvent::RoundEnd {
+            winner: Side::parse(&c["team"]),
+            reason: c["reason"].to_string(),
+            ct_score: c["ct"].parse().ok()?,
+            t_score: c["t"].parse().ok()?,
+        });
+    }
+    if let Some(c) = BOMB_REGEX.captures(msg) {
+        let player = parse_player(&c[1])?;
+        return Some(match &c["action"] {
+            "Planted_The_Bomb" => LogEvent::BombPlanted {
+                player,
+                site: c.name("site").map(|m| m.as_str().to_string()),
+            },
+            _ => LogEvent::BombDefused { player },
+        });
+    }
+    if let Some(c) = SWITCH_REGEX.captures(msg) {
+        return Some(LogEvent::TeamSwitch {
+            player: parse_player(&c[1])?,
+            from: Side::parse(&c["from"]),
+            to: Side::parse(&c["to"]),
+        });
+    }
+    if let Some(c) = CONNECT_REGEX.captures(msg) {
+        return Some(LogEvent::Connected {
+            player: parse_player(&c[1])?,
+            address: c["address"].to_string(),
+        });
+    }
+    if let Some(c) = DISCONNECT_REGEX.captures(msg) {
+        return Some(LogEvent::Disconnected {
+            player: parse_player(&c[1])?,
+            reason: c.name("reason").map(|m| m.as_str().to_string()),
+        });
+    }
+    if let Some(c) = MATCH_START_REGEX.captures(msg) {
+        return Some(LogEvent::MatchStart {
+            map: c["map"].to_string(),
+        });
+    }
+    if let Some(c) = MATCH_STATUS_REGEX.captures(msg) {
+        return Some(LogEvent::MatchStatus {
+            map: c["map"].to_string(),
+            ct_score: c["ct"].parse().ok()?,
+            t_score: c["t"].parse().ok()?,
+            rounds_played: c["rounds"].parse().ok()?,
+        });
+    }
+    if let Some(c) = GAME_OVER_REGEX.captures(msg) {
+        return Some(LogEvent::GameOver {
+            map: c["map"].to_string(),
+            ct_score: c["ct"].parse().ok()?,
+            t_score: c["t"].parse().ok()?,
+            minutes: c["minutes"].parse().ok()?,
+        });
+    }
+    None
+}
+
+fn parse_player(ident: &str) -> Option<LogPlayer> {
+    let c = PLAYER_REGEX.captures(ident)?;
+    Some(LogPlayer {
+        name: c["name"].to_string(),
+        user_id: c["user_id"].parse().ok()?,
+        steam_id: parse_steam_id(&c["steam_id"]),
+        side: c.name("team").map(|m| Side::parse(m.as_str())),
+    })
+}
+
+/// Converts `[U:1:N]` and `STEAM_X:Y:Z` ids to 64-bit Steam ids
+fn parse_steam_id(steam_id: &str) -> Option<u64> {
+    if let Some(account) = steam_id
+        .strip_prefix("[U:1:")
+        .and_then(|s| s.strip_suffix(']'))
+    {
+        return account.parse::<u64>().ok().map(|a| STEAM_ID_64_BASE + a);
+    }
+    let mut parts = steam_id.strip_prefix("STEAM_")?.split(':').skip(1);
+    let y = parts.next()?.parse::<u64>().ok()?;
+    let z = parts.next()?.parse::<u64>().ok()?;
+    Some(STEAM_ID_64_BASE + z * 2 + y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CS2 HTTP and CS:GO file log excerpts, player names, ids and addresses are scrubbed
+    const CS2_LOG: &str = include_str!("../tests/fixtures/cs2.log");
+    const CSGO_LOG: &str = include_str!("../tests/fixtures/csgo.log");
+    const ANN: u64 = 76561198121300630;
+    const CY: u64 = 76561198048483073;
+    const DEE: u64 = 76561199012998916;
+
+    fn player(name: &str, user_id: i32, steam_id: Option<u64>, side: Option<Side>) -> LogPlayer {
+        LogPlayer {
+            name: name.to_string(),
+            user_id,
+            steam_id,
+            side,
+        }
+    }
+
+    /// Events of a log, leaving out the lines the parser does not know
+    fn events(log: &str) -> Vec<LogEvent> {
+        parse_lines(log)
+            .map(|line| line.event)
+            .filter(|event| !matches!(event, LogEvent::Unknown(_)))
+            .collect()
+    }
+
+    fn unknown(log: &str) -> Vec<String> {
+        parse_lines(log)
+            .filter_map(|line| match line.event {
+                LogEvent::Unknown(msg) => Some(msg),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_cs2_log() {
+        let ann = player("ann", 2, Some(ANN), Some(Side::CounterTerrorist));
+        let bot = player("Bo", 3, None, Some(Side::Terrorist));
+        let cy = player("cy ✈", 4, Some(CY), Some(Side::CounterTerrorist));
+        let dee = player("Dee", 5, Some(DEE), Some(Side::Terrorist));
+        assert_eq!(
+            events(CS2_LOG),
+            vec![
+                LogEvent::Connected {
+                    player: player("ann", 2, Some(ANN), Some(Side::Unassigned)),
+                    address: String::new(),
+                },
+                LogEvent::TeamSwitch {
+                    player: player("ann", 2, Some(ANN), None),
+                    from: Side::Unassigned,
+                    to: Side::CounterTerrorist,
+                },
+                LogEvent::Connected {
+                    player: player("Bo", 3, None, Some(Side::Unassigned)),
+                    address: String::new(),
+                },
+                LogEvent::TeamSwitch {
+                    player: player("Bo", 3, None, None),
+                    from: Side::Unassigned,
+                    to: Side::Terrorist,
+                },
+                LogEvent::MatchStart {
+                    map: "de_mirage".to_string(),
+                },
+                LogEvent::RoundStart,
+                LogEvent::Damage {
+                    attacker: ann.clone(),
+                    victim: bot.clone(),
+                    weapon: "m4a1_silencer".to_string(),
+                    damage: 27,
+                    damage_armor: 3,
+                    health: 73,
+                    hitgroup: "chest".to_string(),
+                },
+                LogEvent::Kill {
+                    attacker: ann.clone(),
+                    victim: bot.clone(),
+                    weapon: "m4a1_silencer".to_string(),
+                    headshot: true,
+                },
+                LogEvent::Assist {
+                    assister: cy.clone(),
+                    victim: bot,
+                    flash: true,
+                },
+                LogEvent::BombPlanted {
+                    player: dee.clone(),
+                    site: Some("A".to_string()),
+                },
+                LogEvent::BombDefused {
+                    player: ann.clone(),
+                },
+                LogEvent::RoundEnd {
+                    winner: Side::CounterTerrorist,
+                    reason: "Bomb_Defused".to_string(),
+                    ct_score: 1,
+                    t_score: 0,
+                },
+                LogEvent::MatchStatus {
+                    map: "de_mirage".to_string(),
+                    ct_score: 1,
+                    t_score: 0,
+                    rounds_played: 1,
+                },
+                LogEvent::Chat {
+                    player: ann,
+                    message: "!tac".to_string(),
+                    scope: ChatScope::All,
+                },
+                LogEvent::Chat {
+                    player: cy,
+                    message: r#"he said "go B" now"#.to_string(),
+                    scope: ChatScope::Team,
+                },
+                LogEvent::GameOver {
+                    map: "de_mirage".to_string(),
+                    ct_score: 13,
+                    t_score: 11,
+                    minutes: 43,
+                },
+                LogEvent::Disconnected {
+                    player: dee,
+                    reason: Some("NETWORK_DISCONNECT_DISCONNECT_BY_USER".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cs2_lines_without_an_event_are_unknown() {
+        let unknown = unknown(CS2_LOG);
+        assert_eq!(unknown.len(), 28);
+        for msg in [
+            r#""ann<2><[U:1:161034902]><>" STEAM USERID validated"#,
+            r#"MatchStatus: Team "CT" is currently "team_ann""#,
+            r#""ann<2><[U:1:161034902]><CT>" [-433 -1125 -167] killed other "chicken<176>" [-452 -1006 -167] with "m4a1_silencer""#,
+            r#""Bo<3><BOT><TERRORIST>" blinded for 2.41 by "cy ✈<4><[U:1:88217345]><CT>" from flashbang entindex 212"#,
+            r#"Team "CT" scored "1" with "5" players"#,
+            "JSON_BEGIN{",
+            r#""name": "round_stats","#,
+            "}}JSON_END",
+            r#"World triggered "Round_End""#,
+            "Starting Freeze period",
+        ] {
+            assert!(unknown.iter().any(|m| m == msg), "{msg}");
+        }
+    }
+
+    #[test]
+    fn parses_csgo_log() {
+        let ann = player("ann", 2, Some(ANN), Some(Side::CounterTerrorist));
+        let dee = player("Dee", 5, Some(DEE), Some(Side::Terrorist));
+        assert_eq!(
+            events(CSGO_LOG),
+            vec![
+                LogEvent::Connected {
+                    player: player("ann", 2, Some(ANN), Some(Side::Unassigned)),
+                    address: "10.0.0.12:27005".to_string(),
+                },
+                LogEvent::TeamSwitch {
+                    player: player("ann", 2, Some(ANN), None),
+                    from: Side::Unassigned,
+                    to: Side::CounterTerrorist,
+                },
+                LogEvent::Kill {
+                    attacker: ann,
+                    victim: dee.clone(),
+                    weapon: "m4a1_silencer".to_string(),
+                    headshot: false,
+                },
+                LogEvent::Assist {
+                    assister: player("cy ✈", 4, Some(CY), Some(Side::CounterTerrorist)),
+                    victim: dee.clone(),
+                    flash: false,
+                },
+                LogEvent::BombPlanted {
+                    player: dee,
+                    site: None,
+                },
+                LogEvent::RoundEnd {
+                    winner: Side::Terrorist,
+                    reason: "Target_Bombed".to_string(),
+                    ct_score: 3,
+                    t_score: 4,
+                },
+                LogEvent::Chat {
+                    player: player("spec", 7, Some(76561197997734939), Some(Side::Spectator)),
+                    message: "gg".to_string(),
+                    scope: ChatScope::All,
+                },
+                LogEvent::Disconnected {
+                    player: player("Player", -1, None, Some(Side::Unassigned)),
+                    reason: None,
+                },
+                LogEvent::Chat {
+                    player: player("Console", 0, None, Some(Side::Unassigned)),
+                    message: "round restored".to_string(),
+                    scope: ChatScope::All,
+                },
+                LogEvent::GameOver {
+                    map: "de_mirage".to_string(),
+                    ct_score: 16,
+                    t_score: 12,
+                    minutes: 44,
+                },
+            ]
+        );
+        assert_eq!(
+            unknown(CSGO_LOG),
+            vec![
+                r#"Log file started (file "logs/L000_000_000_000_27015_202203121958_000.log") (game "/home/steam/csgo/csgo") (version "8012")"#,
+                r#"World triggered "Round_End""#,
+                r#"rcon from "10.0.0.5:53120": command "status""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let cs2 = parse_line(CS2_LOG.lines().next().unwrap());
+        assert_eq!(
+            cs2.timestamp,
+            Some(time::macros::datetime!(2023-11-04 20:14:52.304 UTC))
+        );
+        let csgo = parse_line(CSGO_LOG.lines().next().unwrap());
+        assert_eq!(
+            csgo.timestamp,
+            Some(time::macros::datetime!(2022-03-12 19:58:03 UTC))
+        );
+    }
+
+    #[test]
+    fn lines_without_a_prefix_are_unknown() {
+        assert_eq!(
+            parse_line("  not a log line  "),
+            LogLine {
+                timestamp: None,
+                event: LogEvent::Unknown("not a log line".to_string()),
+            }
+        );
+        assert_eq!(parse_lines("\n\n  \n").count(), 0);
+    }
+}
@@ -1,13 +1,23 @@
 #![allow(dead_code)]
 mod auth;
+mod commands;
 mod dathost;
 mod db;
+mod demo;
 mod discord;
 mod errors;
+mod jobs;
+mod live;
+mod logs;
 pub mod models;
+mod registry;
+mod retention;
 pub mod routes;
+mod scoreboard;
 mod steam;
+mod timeline;
 mod utils;
+mod watchdog;
 
 use axum::body::boxed;
 use axum::http::{header, HeaderValue, Method};
@@ -16,8 +26,11 @@ use std::env;
 
 use crate::dathost::DathostClient;
 use crate::discord::DiscordClient;
+use crate::live::LiveServers;
+use crate::registry::CommandRegistry;
 use crate::routes::routes;
 use crate::steam::SteamClient;
+use crate::utils::parse_public_url;
 use dotenvy::dotenv;
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
@@ -43,6 +56,11 @@ pub struct AppState {
     bucket: Bucket,
     discord: DiscordClient,
     steam: SteamClient,
+    live: LiveServers,
+    commands: CommandRegistry,
+    /// Base URL Dathost reaches us on, for webhooks and log ingest. Matches can only be
+    /// created through the API when it is set.
+    public_url: Option<String>,
 }
 
 #[tokio::main]
@@ -121,14 +139,37 @@ async fn main() {
     )
     .expect("unable to connect to S3 bucket");
 
+    let public_url = env::var("PUBLIC_URL")
+        .ok()
+        .map(|url| parse_public_url(&url).unwrap_or_else(|e| panic!("invalid PUBLIC_URL: {}", e)));
+    if public_url.is_none() {
+        tracing::warn!("PUBLIC_URL is not set, matches can not be created through the API");
+    }
+
+    let retention = retention::retention_period()
+        .unwrap_or_else(|e| panic!("invalid DEMO_RETENTION_DAYS: {}", e));
+
+    let commands = CommandRegistry::default();
+    if let Err(error) = commands.reload(&pool).await {
+        tracing::error!("unable to load chat commands: {}", error);
+        std::process::exit(1);
+    }
+
     let shared_state = AppState {
         db: pool,
         dathost,
         bucket,
         discord,
         steam,
+        live: LiveServers::default(),
+        commands,
+        public_url,
     };
 
+    tokio::spawn(jobs::run_worker(shared_state.clone()));
+    tokio::spawn(watchdog::run_watchdog(shared_state.clone()));
+    tokio::spawn(retention::run_retention(shared_state.clone(), retention));
+
     let app = Router::new()
         .nest("/api", routes())
         .with_state(shared_state)
@@ -143,7 +184,7 @@ async fn main() {
     let addr = SocketAddr::from((host, port));
     tracing::info!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
@@ -2,7 +2,10 @@ use derive_more::{AsRef, Deref, Display, From, Into};
 use serde::{Deserialize, Serialize};
 use serde_aux::prelude::*;
 use sqlx::types::time::OffsetDateTime;
-#[derive(Debug, Clone, From, Into, Deref, AsRef, Display, Serialize, Deserialize)]
+use std::str::FromStr;
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, From, Into, Deref, AsRef, Display, Serialize, Deserialize,
+)]
 #[repr(transparent)]
 pub struct ServerId(pub(crate) String);
 #[derive(Clone, Debug, From, Into, Deref, AsRef, Display, Serialize, Deserialize)]
@@ -83,7 +86,7 @@ pub struct Settings {
     pub match_begin_countdown: i64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DathostMatch {
     pub id: i32,
     pub map: String,
@@ -91,13 +94,487 @@ pub struct DathostMatch {
     pub team2_score: i32,
     pub team1_name: String,
     pub team2_name: String,
+    #[serde(with = "time::serde::rfc3339")]
     pub completed_at: OffsetDateTime,
+    pub demo_url: Option<String>,
+    pub dathost_match_id: Option<String>,
+    pub server_id: Option<String>,
+    /// Set for matches Dathost cancelled, these are left out of statistics
+    pub cancel_reason: Option<String>,
+    /// Demos of kept matches are never removed by the retention job
+    pub keep_demo: bool,
+    #[serde(skip)]
+    pub server_stopped_at: Option<OffsetDateTime>,
+    #[serde(skip)]
+    pub summary_posted_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchPlayerStats {
+    pub steam_id: String,
+    pub team: String,
+    pub kills: i32,
+    pub assists: i32,
+    pub deaths: i32,
+    pub adr: f64,
+    pub n2ks: i32,
+    pub n3ks: i32,
+    pub n4ks: i32,
+    pub n5ks: i32,
+    pub kills_with_headshot: i32,
+    pub kills_with_pistol: i32,
+    pub kills_with_sniper: i32,
+    pub damage_dealt: i32,
+    pub entry_attempts: i32,
+    pub entry_successes: i32,
+    pub flashes_thrown: i32,
+    pub flashes_successful: i32,
+    pub flashes_enemies_blinded: i32,
+    pub utility_thrown: i32,
+    pub utility_damage: i32,
+    #[serde(rename = "1vX_attempts")]
+    pub n1vx_attempts: i32,
+    #[serde(rename = "1vX_wins")]
+    pub n1vx_wins: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchTeam {
+    pub team: String,
+    pub name: String,
+    pub score: i32,
+    pub players: Vec<MatchPlayerStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MatchDetails {
+    #[serde(flatten)]
+    pub dathost_match: DathostMatch,
+    pub teams: Vec<MatchTeam>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundDetails {
+    #[serde(skip)]
+    pub id: i32,
+    pub round_number: i32,
+    pub winner: String,
+    pub win_reason: String,
+    pub ct_score: i32,
+    pub t_score: i32,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub started_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub ended_at: OffsetDateTime,
+    pub duration_secs: Option<f64>,
+    pub kills: Vec<KillDetails>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KillDetails {
+    #[serde(skip)]
+    pub round_id: i32,
+    pub attacker_steam_id: Option<String>,
+    pub victim_steam_id: Option<String>,
+    pub weapon: String,
+    pub headshot: bool,
+    #[serde(with = "time::serde::rfc3339")]
+    pub killed_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct MatchListParams {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    pub map: Option<String>,
+    pub team: Option<String>,
+    pub steam_id: Option<i64>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+}
+
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub items: Vec<T>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobKind {
+    StopServer,
+    FetchDemo,
+    UploadDemo,
+    PostSummary,
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobKind::StopServer => "stop_server",
+            JobKind::FetchDemo => "fetch_demo",
+            JobKind::UploadDemo => "upload_demo",
+            JobKind::PostSummary => "post_summary",
+        }
+    }
+
+    /// The job queued once this one completes. The demo is fetched while the
+    /// server is still up, cancelled matches skip it altogether.
+    pub fn next(&self, cancelled: bool) -> Option<Self> {
+        match self {
+            JobKind::FetchDemo => Some(JobKind::StopServer),
+            JobKind::StopServer if cancelled => Some(JobKind::PostSummary),
+            JobKind::StopServer => Some(JobKind::UploadDemo),
+            JobKind::UploadDemo => Some(JobKind::PostSummary),
+            JobKind::PostSummary => None,
+        }
+    }
+
+    /// The demo is a nice to have, once its jobs give up the server is still
+    /// stopped and the summary still posted
+    pub fn is_optional(&self) -> bool {
+        matches!(self, JobKind::FetchDemo | JobKind::UploadDemo)
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "stop_server" => Ok(JobKind::StopServer),
+            "fetch_demo" => Ok(JobKind::FetchDemo),
+            "upload_demo" => Ok(JobKind::UploadDemo),
+            "post_summary" => Ok(JobKind::PostSummary),
+            _ => Err(format!("unknown job kind '{}'", kind)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: i32,
+    pub match_id: i32,
+    pub kind: String,
+    pub attempts: i32,
+    /// The demo size seen by the previous poll of a `fetch_demo` job
+    pub seen_size: Option<i64>,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegisteredServer {
+    pub server_id: String,
+    pub name: Option<String>,
+    pub ingest_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct RegisterServer {
+    pub server_id: String,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ServerRegistration {
+    pub server_id: String,
+    pub name: Option<String>,
+    pub ingest_token: String,
+    pub ingest_path: String,
+}
+
+/// Who may use a chat command, each role includes the ones below it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Player,
+    Captain,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Player => "player",
+            Role::Captain => "captain",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "player" => Ok(Role::Player),
+            "captain" => Ok(Role::Captain),
+            "admin" => Ok(Role::Admin),
+            _ => Err(format!("unknown role '{}'", role)),
+        }
+    }
+}
+
+/// A chat command, typed in game as `!<name>` or `!<alias>`.
+/// Commands without console lines configure the built-in command of the same name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCommand {
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub role: Role,
+    #[serde(default)]
+    pub cooldown_seconds: u32,
+    /// Sent to the server console in order, `{player}` and `{args}` are substituted
+    #[serde(default)]
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateMatch {
+    pub server_id: String,
+    pub map: String,
+    pub team1: CreateMatchTeam,
+    pub team2: CreateMatchTeam,
+    /// Seconds players have to connect before the match is cancelled
+    pub connect_time: Option<i64>,
+    #[serde(default)]
+    pub knife_round: bool,
+    #[serde(default = "bool_true")]
+    pub wait_for_gotv: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateMatchTeam {
+    pub name: String,
+    /// 64-bit Steam id of the player given the captain role for this match
+    pub captain: Option<String>,
+    /// 64-bit Steam ids
+    #[serde(default)]
+    pub players: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledMatch {
+    pub id: i32,
+    pub dathost_match_id: String,
+    pub server_id: String,
+    pub map: String,
+    pub team1_name: String,
+    pub team2_name: String,
+    pub connect: String,
+    pub match_id: Option<i32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+/// Request body of Dathost's CS2 match creation API
+#[derive(Debug, Clone, Serialize)]
+pub struct NewDathostMatch {
+    pub game_server_id: String,
+    pub team1: NewDathostTeam,
+    pub team2: NewDathostTeam,
+    pub players: Vec<NewDathostPlayer>,
+    pub settings: NewDathostSettings,
+    pub webhooks: DathostWebhooks,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewDathostTeam {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewDathostPlayer {
+    pub steam_id_64: String,
+    pub team: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NewDathostSettings {
+    pub map: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connect_time: Option<i64>,
+    pub enable_knife_round: bool,
+    pub wait_for_gotv: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DathostWebhooks {
+    pub match_end_url: String,
+    pub authorization_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreatedDathostMatch {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DathostServer {
+    pub id: String,
+    pub name: String,
+    pub ip: String,
+    pub on: bool,
+    pub booting: bool,
+    pub players_online: u32,
+    pub ports: DathostPorts,
+    pub cs2_settings: DathostCs2Settings,
+    /// Live server info such as the current map, as reported by Dathost
+    pub status: Vec<DathostStatusEntry>,
+}
+
+impl DathostServer {
+    pub fn connect(&self) -> String {
+        let mut connect = format!("connect {}:{}", self.ip, self.ports.game);
+        if !self.cs2_settings.password.is_empty() {
+            connect.push_str(&format!("; password {}", self.cs2_settings.password));
+        }
+        connect
+    }
+
+    /// The map being played, or the map the server starts on when it is offline
+    pub fn map(&self) -> Option<String> {
+        self.status
+            .iter()
+            .find(|s| s.key.eq_ignore_ascii_case("map"))
+            .map(|s| s.value.clone())
+            .or_else(|| {
+                Some(self.cs2_settings.mapgroup_start_map.clone()).filter(|m| !m.is_empty())
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DathostStatusEntry {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub server_id: String,
+    pub name: String,
+    pub online: bool,
+    pub booting: bool,
+    pub players_online: u32,
+    pub map: Option<String>,
+    pub connect: String,
+}
+
+impl From<DathostServer> for ServerStatus {
+    fn from(server: DathostServer) -> Self {
+        ServerStatus {
+            online: server.on,
+            booting: server.booting,
+            players_online: server.players_online,
+            map: server.map(),
+            connect: server.connect(),
+            server_id: server.id,
+            name: server.name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DathostPorts {
+    pub game: u16,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DathostCs2Settings {
+    pub password: String,
+    pub mapgroup_start_map: String,
+}
+
+/// The size and sha256 of a stream of bytes
+#[derive(Debug, Clone)]
+pub struct Checksum {
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// A demo uploaded to the bucket, `state` is `stored` until retention deletes it.
+/// A file on the server that turned out not to be a demo is kept as `invalid`, without an object.
+#[derive(Debug, Clone, Serialize)]
+pub struct Demo {
+    pub id: i32,
+    pub match_id: i32,
+    pub object_key: Option<String>,
+    /// The demo as it was on the server
+    pub demo_size: Option<i64>,
+    pub demo_sha256: Option<String>,
+    /// The object in the bucket, differs from the demo when it was compressed
+    pub object_size: Option<i64>,
+    pub object_sha256: Option<String>,
+    pub state: String,
+    /// Read from the demo's header, `HL2DEMO` or `PBDEMS2`
+    pub format: Option<String>,
+    pub map_name: Option<String>,
+    pub server_name: Option<String>,
+    pub playback_ticks: Option<i32>,
+    pub playback_seconds: Option<f32>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub uploaded_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub deleted_at: Option<OffsetDateTime>,
+}
+
+impl Demo {
+    /// The key of the demo in the bucket, as long as it is there
+    pub fn stored_key(&self) -> Option<&str> {
+        self.object_key
+            .as_deref()
+            .filter(|_| self.state == "stored")
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DemoDownload {
+    #[serde(flatten)]
+    pub demo: Demo,
+    pub url: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct KeepDemo {
+    pub keep: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DathostFile {
+    pub path: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreParams {
+    pub round: u32,
+}
+
+#[derive(Serialize)]
+pub struct RestoredBackup {
+    pub server_id: String,
+    pub round: u32,
+    pub file: String,
 }
 
 #[derive(Deserialize)]
 pub struct MatchEndParams {
     pub wait_for_gotv: Option<bool>,
 }
+
 #[derive(Serialize, Deserialize)]
 pub struct SteamUser {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -129,3 +606,135 @@ pub struct ActionRow {
     pub component_type: i8,
     pub components: Vec<MessageComponent>,
 }
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Embed {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<EmbedField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<EmbedImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<EmbedImage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<EmbedFooter>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    pub timestamp: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedField {
+    pub name: String,
+    pub value: String,
+    pub inline: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedImage {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedFooter {
+    pub text: String,
+}
+
+#[derive(Default, Deserialize)]
+pub struct StatsParams {
+    pub map: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+    pub min_matches: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LeaderboardMetric {
+    #[default]
+    Adr,
+    Kd,
+    HsPct,
+    EntryRate,
+    ClutchRate,
+    Kills,
+}
+
+impl LeaderboardMetric {
+    pub fn value(&self, stats: &PlayerStatsSummary) -> f64 {
+        match self {
+            LeaderboardMetric::Adr => stats.adr,
+            LeaderboardMetric::Kd => stats.kd,
+            LeaderboardMetric::HsPct => stats.hs_pct,
+            LeaderboardMetric::EntryRate => stats.entry_rate,
+            LeaderboardMetric::ClutchRate => stats.n1vx_win_rate,
+            LeaderboardMetric::Kills => stats.kills as f64,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct LeaderboardParams {
+    #[serde(default)]
+    pub metric: LeaderboardMetric,
+    pub map: Option<String>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub since: Option<OffsetDateTime>,
+    pub min_matches: Option<i64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct LeaderboardEntry {
+    pub rank: usize,
+    pub steam_id: String,
+    pub name: Option<String>,
+    pub matches: i64,
+    pub value: f64,
+}
+
+#[derive(Serialize)]
+pub struct Leaderboard {
+    pub metric: LeaderboardMetric,
+    pub map: Option<String>,
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStatsSummary {
+    pub steam_id: String,
+    pub matches: i64,
+    pub kills: i64,
+    pub deaths: i64,
+    pub assists: i64,
+    pub kd: f64,
+    pub adr: f64,
+    pub hs_pct: f64,
+    pub n2ks: i64,
+    pub n3ks: i64,
+    pub n4ks: i64,
+    pub n5ks: i64,
+    pub entry_attempts: i64,
+    pub entry_successes: i64,
+    pub entry_rate: f64,
+    pub flashes_thrown: i64,
+    pub flashes_successful: i64,
+    pub flashes_enemies_blinded: i64,
+    pub utility_thrown: i64,
+    pub utility_damage: i64,
+    #[serde(rename = "1vX_attempts")]
+    pub n1vx_attempts: i64,
+    #[serde(rename = "1vX_wins")]
+    pub n1vx_wins: i64,
+    #[serde(rename = "1vX_win_rate")]
+    pub n1vx_win_rate: f64,
+}
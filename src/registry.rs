@@ -0,0 +1,106 @@
+use crate::db::get_chat_commands;
+use crate::errors::Error;
+use crate::models::{ChatCommand, Role};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, RwLock};
+
+/// Built-in commands and the role they need unless configured otherwise
+const BUILT_IN: [(&str, Role); 10] = [
+    ("tech", Role::Player),
+    ("pause", Role::Player),
+    ("tac", Role::Player),
+    ("unpause", Role::Player),
+    ("ready", Role::Player),
+    ("unready", Role::Player),
+    ("score", Role::Player),
+    ("help", Role::Player),
+    ("forcestart", Role::Admin),
+    ("restore", Role::Admin),
+];
+
+/// The chat commands available on every server, keyed by name and alias.
+/// Built from the built-in commands, then `COMMANDS_FILE`, then the database,
+/// later sources replacing commands of the same name.
+#[derive(Clone, Default)]
+pub struct CommandRegistry(Arc<RwLock<Commands>>);
+
+#[derive(Default)]
+struct Commands {
+    commands: Vec<Arc<ChatCommand>>,
+    lookup: HashMap<String, Arc<ChatCommand>>,
+}
+
+impl CommandRegistry {
+    /// Finds a command by name or alias, without the leading `!`
+    pub fn get(&self, name: &str) -> Option<Arc<ChatCommand>> {
+        let commands = self.0.read().unwrap_or_else(|e| e.into_inner());
+        commands.lookup.get(&name.to_lowercase()).cloned()
+    }
+
+    pub fn list(&self) -> Vec<ChatCommand> {
+        let commands = self.0.read().unwrap_or_else(|e| e.into_inner());
+        commands.commands.iter().map(|c| (**c).clone()).collect()
+    }
+
+    /// Rebuilds the registry, the current commands are kept if any source is invalid
+    pub async fn reload(&self, db: &PgPool) -> Result<usize, Error> {
+        let mut commands: Vec<ChatCommand> = BUILT_IN
+            .iter()
+            .map(|(name, role)| ChatCommand {
+                name: name.to_string(),
+                aliases: vec![],
+                role: *role,
+                cooldown_seconds: 0,
+                lines: vec![],
+            })
+            .collect();
+        if let Ok(path) = env::var("COMMANDS_FILE") {
+            let file = tokio::fs::read(&path).await?;
+            commands.extend(serde_json::from_slice::<Vec<ChatCommand>>(&file)?);
+        }
+        commands.extend(get_chat_commands(db).await?);
+
+        let mut by_name: HashMap<String, ChatCommand> = HashMap::new();
+        let mut order = vec![];
+        for mut command in commands {
+            command.name = command.name.trim_start_matches('!').to_lowercase();
+            if command.name.is_empty() {
+                tracing::warn!("skipping a chat command without a name");
+                continue;
+            }
+            if !by_name.contains_key(&command.name) {
+                order.push(command.name.clone());
+            }
+            by_name.insert(command.name.clone(), command);
+        }
+        let commands: Vec<_> = order
+            .into_iter()
+            .filter_map(|name| by_name.remove(&name))
+            .map(Arc::new)
+            .collect();
+        let mut lookup = HashMap::new();
+        for command in &commands {
+            lookup.insert(command.name.clone(), command.clone());
+        }
+        // names win over aliases so an alias can never hide a command
+        for command in &commands {
+            for alias in &command.aliases {
+                let alias = alias.trim_start_matches('!').to_lowercase();
+                if lookup.contains_key(&alias) {
+                    tracing::warn!(
+                        "ignoring alias '{}' of '{}', already taken",
+                        alias,
+                        command.name
+                    );
+                    continue;
+                }
+                lookup.insert(alias, command.clone());
+            }
+        }
+        let count = commands.len();
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = Commands { commands, lookup };
+        Ok(count)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::db::{get_expired_demos, set_demo_deleted};
+use crate::errors::Error;
+use crate::utils::parse_env;
+use crate::AppState;
+use sqlx::types::time::OffsetDateTime;
+use std::time::Duration;
+use tokio::time::sleep;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How long demos are kept, from `DEMO_RETENTION_DAYS`. `None` when it is unset, an error
+/// when it is not a number of days the expiry cutoff can be computed for.
+pub fn retention_period() -> Result<Option<Duration>, String> {
+    let Some(days) = parse_env::<u64>("DEMO_RETENTION_DAYS")? else {
+        return Ok(None);
+    };
+    let period = days
+        .checked_mul(SECS_PER_DAY)
+        .map(Duration::from_secs)
+        .filter(|period| expiry_cutoff(*period).is_some())
+        .ok_or_else(|| format!("{} days is out of range", days))?;
+    Ok(Some(period))
+}
+
+/// Deletes demos older than the retention period from the bucket, skipping kept matches.
+/// Does nothing unless a retention period is configured.
+pub async fn run_retention(state: AppState, period: Option<Duration>) {
+    let Some(period) = period else {
+        tracing::info!("demo retention disabled");
+        return;
+    };
+    loop {
+        if let Err(e) = delete_expired_demos(&state, period).await {
+            tracing::error!("demo retention failed: {}", e);
+        }
+        sleep(CHECK_INTERVAL).await;
+    }
+}
+
+/// Demos of matches completed before this have expired, `None` when it would be earlier
+/// than the earliest date that can be represented
+fn expiry_cutoff(period: Duration) -> Option<OffsetDateTime> {
+    OffsetDateTime::now_utc().checked_sub(period.try_into().ok()?)
+}
+
+async fn delete_expired_demos(state: &AppState, period: Duration) -> Result<(), Error> {
+    let Some(cutoff) = expiry_cutoff(period) else {
+        tracing::error!("demo retention period of {:?} is out of range", period);
+        return Ok(());
+    };
+    for demo in get_expired_demos(&state.db, cutoff).await? {
+        let Some(key) = demo.stored_key() else {
+            continue;
+        };
+        let status = state.bucket.delete_object(key).await?.status_code();
+        if !(200..300).contains(&status) {
+            tracing::error!("unable to delete demo '{}': s3 status {}", key, status);
+            continue;
+        }
+        set_demo_deleted(&state.db, demo.id).await?;
+        tracing::info!("deleted demo '{}' of match {}", key, demo.match_id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retention_period_must_have_a_cutoff() {
+        assert!(expiry_cutoff(Duration::from_secs(30 * SECS_PER_DAY)).is_some());
+        assert!(expiry_cutoff(Duration::from_secs(u64::MAX)).is_none());
+    }
+}
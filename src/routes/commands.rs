@@ -0,0 +1,52 @@
+use crate::db::{delete_chat_command, upsert_chat_command};
+use crate::errors::Error;
+use crate::models::ChatCommand;
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+
+pub fn command_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_commands).post(save_command))
+        .route("/reload", post(reload_commands))
+        .route("/:name", delete(remove_command))
+}
+
+pub async fn list_commands(state: State<AppState>) -> Json<Vec<ChatCommand>> {
+    Json(state.commands.list())
+}
+
+/// Creates or replaces a command stored in the database
+pub async fn save_command(
+    state: State<AppState>,
+    Json(mut command): Json<ChatCommand>,
+) -> Result<Json<Vec<ChatCommand>>, Error> {
+    command.name = command.name.trim_start_matches('!').to_lowercase();
+    if command.name.is_empty() || command.name.contains(char::is_whitespace) {
+        return Err(Error::BadRequest(
+            "command names can not be empty or contain spaces".to_string(),
+        ));
+    }
+    upsert_chat_command(&state.db, &command).await?;
+    state.commands.reload(&state.db).await?;
+    tracing::info!("saved chat command '{}'", command.name);
+    Ok(Json(state.commands.list()))
+}
+
+pub async fn remove_command(
+    state: State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<ChatCommand>>, Error> {
+    delete_chat_command(&state.db, &name.to_lowercase()).await?;
+    state.commands.reload(&state.db).await?;
+    tracing::info!("deleted chat command '{}'", name);
+    Ok(Json(state.commands.list()))
+}
+
+/// Picks up changes to `COMMANDS_FILE` or the database without a restart
+pub async fn reload_commands(state: State<AppState>) -> Result<Json<Vec<ChatCommand>>, Error> {
+    let count = state.commands.reload(&state.db).await?;
+    tracing::info!("reloaded {} chat commands", count);
+    Ok(Json(state.commands.list()))
+}
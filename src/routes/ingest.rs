@@ -1,61 +1,98 @@
+use crate::auth::constant_time_eq;
+use crate::commands::handle_chat;
+use crate::db::get_registered_servers;
+use crate::logs::{parse_lines, LogEvent, Side};
 use crate::models::ServerId;
+use crate::timeline::record_event;
 use crate::AppState;
-use axum::extract::State;
+use axum::extract::{ConnectInfo, Path, State};
 use axum::headers::UserAgent;
+use axum::http::HeaderMap;
 use axum::response::IntoResponse;
 use axum::routing::post;
 use axum::{Router, TypedHeader};
-use regex::Regex;
 use reqwest::StatusCode;
-use std::env;
+use std::net::SocketAddr;
 
 pub fn ingest_routes() -> Router<AppState> {
-    Router::new().route("/logs", post(post_logs))
+    Router::new().route("/logs/:token", post(post_logs))
 }
 
 pub async fn post_logs(
     TypedHeader(user_agent): TypedHeader<UserAgent>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     state: State<AppState>,
+    Path(token): Path<String>,
     body: String,
 ) -> impl IntoResponse {
+    let forwarded_for = headers
+        .get("x-forwarded-for")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("-");
     if !user_agent.to_string().contains("Valve/Steam") {
+        tracing::warn!(
+            "rejected log ingest from {} (forwarded for {}): unexpected user agent '{}'",
+            addr,
+            forwarded_for,
+            user_agent
+        );
         return StatusCode::UNAUTHORIZED;
     }
-    let server_id = &ServerId(env::var("DATHOST_SERVER_ID").unwrap());
-    let lines = body.split("\n");
-    for line in lines {
-        let said_regex = Regex::new(r#"say "(?<cmd>.+?)""#).unwrap();
-        let Some(captures) = said_regex.captures(line) else {
-            continue;
-        };
-        let cmd = &captures["cmd"];
-        let send_result = match cmd {
-            "!tech" => {
-                state
-                    .dathost
-                    .send_console_msg(&server_id, "mp_pause_match".to_string())
-                    .await
-            }
-            "!unpause" => {
-                state
-                    .dathost
-                    .send_console_msg(&server_id, "mp_unpause_match".to_string())
-                    .await
+    let servers = match get_registered_servers(&state.db).await {
+        Ok(servers) => servers,
+        Err(e) => {
+            tracing::error!("unable to load registered servers: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    };
+    // every registered token is compared so the response time does not depend on
+    // which server, if any, the token belongs to
+    let server = servers.into_iter().fold(None, |found, server| {
+        let matches = constant_time_eq(server.ingest_token.as_bytes(), token.as_bytes());
+        if matches {
+            Some(server)
+        } else {
+            found
+        }
+    });
+    let Some(server) = server else {
+        tracing::warn!(
+            "rejected log ingest from {} (forwarded for {}): unknown token",
+            addr,
+            forwarded_for
+        );
+        return StatusCode::UNAUTHORIZED;
+    };
+    let server_id = &ServerId(server.server_id);
+    for line in parse_lines(&body) {
+        if let Err(e) = record_event(&state, server_id, &line).await {
+            tracing::error!("unable to record log event for {}: {}", server_id, e);
+        }
+        let (player, message) = match line.event {
+            LogEvent::Chat {
+                player, message, ..
+            } => (player, message),
+            // players that leave or move to spectators are no longer ready
+            LogEvent::Disconnected { player, .. }
+            | LogEvent::TeamSwitch {
+                player,
+                to: Side::Spectator | Side::Unassigned,
+                ..
+            } => {
+                if let Some(steam_id) = player.steam_id {
+                    let mut live = state.live.lock();
+                    if let Some(server) = live.get_mut(server_id) {
+                        server.ready.remove(&steam_id);
+                    }
+                }
+                continue;
             }
             _ => continue,
         };
-        let Ok(status_code) = send_result else {
-            return StatusCode::INTERNAL_SERVER_ERROR;
-        };
-
-        if cmd == "!tech" {
-            if let Err(s) = state.dathost.send_console_msg(&server_id, "say Either team can type !unpause to immediately resume the match. Be sure both teams are ready before unpausing.".to_string()).await {
-                tracing::error!("{:#?}", s);
-            }
-        }
-
-        if status_code.as_u16() != 200 {
-            tracing::error!("send console msg status: {}", status_code)
+        if let Err(e) = handle_chat(&state, server_id, &player, &message).await {
+            // the rest of the batch still has to be recorded
+            tracing::error!("unable to handle '{}' on {}: {}", message, server_id, e);
         }
     }
     StatusCode::OK
@@ -0,0 +1,62 @@
+use crate::db::get_player_stats;
+use crate::errors::Error;
+use crate::models::{Leaderboard, LeaderboardEntry, LeaderboardParams, StatsParams};
+use crate::AppState;
+use axum::extract::{Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+
+const DEFAULT_LIMIT: usize = 10;
+// Steam's GetPlayerSummaries accepts at most 100 ids per request
+const MAX_LIMIT: usize = 100;
+
+pub fn leaderboard_routes() -> Router<AppState> {
+    Router::new().route("/", get(leaderboard))
+}
+
+pub async fn leaderboard(
+    state: State<AppState>,
+    Query(params): Query<LeaderboardParams>,
+) -> Result<Json<Leaderboard>, Error> {
+    let metric = params.metric;
+    let stats_params = StatsParams {
+        map: params.map.clone(),
+        from: params.since,
+        to: None,
+        min_matches: params.min_matches,
+    };
+    let mut stats = get_player_stats(&state.db, None, &stats_params).await?;
+    stats.sort_by(|a, b| metric.value(b).total_cmp(&metric.value(a)));
+    stats.truncate(params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+
+    let steam_ids = stats
+        .iter()
+        .filter_map(|s| s.steam_id.parse::<u64>().ok())
+        .collect();
+    let steam_users = state.steam.get_player_summaries(steam_ids).await?;
+
+    let mut entries: Vec<LeaderboardEntry> = Vec::with_capacity(stats.len());
+    for (i, s) in stats.iter().enumerate() {
+        let value = metric.value(s);
+        // tied players share the rank of the first player with that value
+        let rank = match entries.last() {
+            Some(prev) if prev.value == value => prev.rank,
+            _ => i + 1,
+        };
+        entries.push(LeaderboardEntry {
+            rank,
+            name: steam_users
+                .iter()
+                .find(|u| u.steamid.to_string() == s.steam_id)
+                .map(|u| u.personaname.clone()),
+            steam_id: s.steam_id.clone(),
+            matches: s.matches,
+            value,
+        });
+    }
+    Ok(Json(Leaderboard {
+        metric,
+        map: params.map,
+        entries,
+    }))
+}
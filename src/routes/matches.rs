@@ -0,0 +1,286 @@
+use crate::auth::generate_token;
+use crate::commands::send;
+use crate::db::{
+    count_matches, create_scheduled_match, get_demo, get_kill_events, get_match, get_match_stats,
+    get_matches, get_rounds, register_server, set_match_keep_demo,
+};
+use crate::errors::Error;
+use crate::models::{
+    CreateMatch, DathostMatch, DathostWebhooks, DemoDownload, KeepDemo, MatchDetails,
+    MatchListParams, MatchTeam, NewDathostMatch, NewDathostPlayer, NewDathostSettings,
+    NewDathostTeam, Page, RoundDetails, ScheduledMatch, ServerId,
+};
+use crate::utils::{env_or, presign_demo, MAX_PRESIGN_SECS};
+use crate::AppState;
+use axum::extract::{Path, Query, State};
+use axum::routing::{get, put};
+use axum::{Json, Router};
+use sqlx::types::time::OffsetDateTime;
+use std::env;
+use std::time::Duration;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+/// Holds the log address, kept apart from the admin's own config
+const LOG_CONFIG_PATH: &str = "cfg/scrimbot_logs.cfg";
+const EXEC_LOG_CONFIG: &str = "exec scrimbot_logs";
+/// Run by the game server on every boot, so log ingest survives restarts
+const AUTOEXEC_PATH: &str = "cfg/autoexec.cfg";
+
+pub fn match_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_matches).post(create_match))
+        .route("/:id", get(match_details))
+        .route("/:id/rounds", get(match_rounds))
+        .route("/:id/keep", put(keep_demo))
+        .route("/:id/demo", get(demo_download))
+}
+
+pub async fn list_matches(
+    state: State<AppState>,
+    Query(params): Query<MatchListParams>,
+) -> Result<Json<Page<DathostMatch>>, Error> {
+    let page = params.page.unwrap_or(1).max(1);
+    let per_page = params
+        .per_page
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let items = get_matches(&state.db, &params, per_page, page_offset(page, per_page)).await?;
+    let total = count_matches(&state.db, &params).await?;
+    Ok(Json(Page {
+        page,
+        per_page,
+        total,
+        items,
+    }))
+}
+
+/// Rows to skip for a page, saturating so a huge page number can not overflow
+fn page_offset(page: i64, per_page: i64) -> i64 {
+    page.saturating_sub(1).saturating_mul(per_page)
+}
+
+/// Creates a match on Dathost with its end of match webhook and server logs pointed at us
+pub async fn create_match(
+    state: State<AppState>,
+    Json(request): Json<CreateMatch>,
+) -> Result<Json<ScheduledMatch>, Error> {
+    let Some(public_url) = &state.public_url else {
+        return Err(Error::PublicUrlMissing);
+    };
+    let mut players = vec![];
+    for (team, roster) in [("team1", &request.team1), ("team2", &request.team2)] {
+        if let Some(captain) = &roster.captain {
+            if captain.parse::<u64>().is_err() {
+                return Err(Error::BadRequest(format!(
+                    "invalid captain steam id '{}'",
+                    captain
+                )));
+            }
+        }
+        for steam_id in &roster.players {
+            if steam_id.parse::<u64>().is_err() {
+                return Err(Error::BadRequest(format!(
+                    "invalid steam id '{}'",
+                    steam_id
+                )));
+            }
+            players.push(NewDathostPlayer {
+                steam_id_64: steam_id.clone(),
+                team: team.to_string(),
+            });
+        }
+    }
+    let new_match = NewDathostMatch {
+        game_server_id: request.server_id.clone(),
+        team1: NewDathostTeam {
+            name: request.team1.name.clone(),
+        },
+        team2: NewDathostTeam {
+            name: request.team2.name.clone(),
+        },
+        players,
+        settings: NewDathostSettings {
+            map: request.map.clone(),
+            connect_time: request.connect_time,
+            enable_knife_round: request.knife_round,
+            wait_for_gotv: request.wait_for_gotv,
+        },
+        webhooks: DathostWebhooks {
+            match_end_url: format!(
+                "{}/api/webhooks/match-end?wait_for_gotv={}",
+                public_url, request.wait_for_gotv
+            ),
+            authorization_header: env::var("AUTH_TOKEN")
+                .ok()
+                .map(|token| format!("TOKEN {}", token)),
+        },
+    };
+    let server_id = ServerId(request.server_id.clone());
+    // servers we create matches on are registered for log ingest, keeping an existing token
+    let server = register_server(&state.db, &request.server_id, None, &generate_token()).await?;
+    let ingest_url = format!("{}/api/ingest/logs/{}", public_url, server.ingest_token);
+    let log_address = format!("logaddress_add_http \"{}\"", ingest_url);
+    write_log_config(&state, &server_id, &log_address).await?;
+    let created = state.dathost.create_match(&new_match).await?;
+    tracing::info!("created dathost match {} on {}", created.id, server_id);
+
+    // a server that is already running has executed its config, point it at us right away
+    if let Err(error) = send(&state, &server_id, EXEC_LOG_CONFIG).await {
+        tracing::warn!("unable to set the log address on {}: {}", server_id, error);
+    }
+    match schedule_match(&state, &server_id, &created.id, &request).await {
+        Ok(scheduled) => Ok(Json(scheduled)),
+        Err(error) => {
+            // don't leave a match running on Dathost that we have no record of
+            match state.dathost.cancel_match(&created.id).await {
+                Ok(status) if status.is_success() => {}
+                Ok(status) => {
+                    tracing::error!("unable to cancel dathost match {}: {}", created.id, status)
+                }
+                Err(cancel_error) => tracing::error!(
+                    "unable to cancel dathost match {}: {}",
+                    created.id,
+                    cancel_error
+                ),
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Writes the log address to its own config and makes sure the server's autoexec runs it,
+/// appending to an existing autoexec rather than replacing it
+async fn write_log_config(
+    state: &AppState,
+    server_id: &ServerId,
+    log_address: &str,
+) -> Result<(), Error> {
+    let status = state
+        .dathost
+        .upload_file(
+            server_id,
+            LOG_CONFIG_PATH,
+            format!("{}\n", log_address).into_bytes(),
+        )
+        .await?;
+    if !status.is_success() {
+        return Err(Error::Dathost(status));
+    }
+    let autoexec = state
+        .dathost
+        .find_file(server_id, AUTOEXEC_PATH)
+        .await?
+        .unwrap_or_default();
+    let autoexec = String::from_utf8_lossy(&autoexec);
+    if autoexec.lines().any(|line| line.trim() == EXEC_LOG_CONFIG) {
+        return Ok(());
+    }
+    let mut contents = autoexec.into_owned();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(EXEC_LOG_CONFIG);
+    contents.push('\n');
+    let status = state
+        .dathost
+        .upload_file(server_id, AUTOEXEC_PATH, contents.into_bytes())
+        .await?;
+    if !status.is_success() {
+        return Err(Error::Dathost(status));
+    }
+    Ok(())
+}
+
+async fn schedule_match(
+    state: &AppState,
+    server_id: &ServerId,
+    dathost_match_id: &str,
+    request: &CreateMatch,
+) -> Result<ScheduledMatch, Error> {
+    let connect = state.dathost.get_server(server_id).await?.connect();
+    create_scheduled_match(&state.db, dathost_match_id, &connect, request).await
+}
+
+/// A short lived download link for the match's demo
+pub async fn demo_download(
+    state: State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<DemoDownload>, Error> {
+    let demo = get_demo(&state.db, id).await?.ok_or(Error::NotFound)?;
+    let key = demo.stored_key().ok_or(Error::NotFound)?;
+    let expiry_secs = env_or::<u32>("DEMO_URL_EXPIRY_SECONDS", 3600).min(MAX_PRESIGN_SECS);
+    let url = presign_demo(&state.bucket, key, expiry_secs)?;
+    Ok(Json(DemoDownload {
+        demo,
+        url,
+        expires_at: OffsetDateTime::now_utc() + Duration::from_secs(expiry_secs as u64),
+    }))
+}
+
+/// Marks a match's demo to be kept past the retention period, or releases it again
+pub async fn keep_demo(
+    state: State<AppState>,
+    Path(id): Path<i32>,
+    Json(params): Json<KeepDemo>,
+) -> Result<Json<DathostMatch>, Error> {
+    set_match_keep_demo(&state.db, id, params.keep).await?;
+    Ok(Json(get_match(&state.db, id).await?))
+}
+
+pub async fn match_details(
+    state: State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<MatchDetails>, Error> {
+    let dathost_match = get_match(&state.db, id).await?;
+    let stats = get_match_stats(&state.db, id).await?;
+    let (team1_players, team2_players) = stats.into_iter().partition(|p| p.team == "team1");
+    let teams = vec![
+        MatchTeam {
+            team: "team1".to_string(),
+            name: dathost_match.team1_name.clone(),
+            score: dathost_match.team1_score,
+            players: team1_players,
+        },
+        MatchTeam {
+            team: "team2".to_string(),
+            name: dathost_match.team2_name.clone(),
+            score: dathost_match.team2_score,
+            players: team2_players,
+        },
+    ];
+    Ok(Json(MatchDetails {
+        dathost_match,
+        teams,
+    }))
+}
+
+pub async fn match_rounds(
+    state: State<AppState>,
+    Path(id): Path<i32>,
+) -> Result<Json<Vec<RoundDetails>>, Error> {
+    get_match(&state.db, id).await?;
+    let mut rounds = get_rounds(&state.db, id).await?;
+    for kill in get_kill_events(&state.db, id).await? {
+        if let Some(round) = rounds.iter_mut().find(|r| r.id == kill.round_id) {
+            round.kills.push(kill);
+        }
+    }
+    Ok(Json(rounds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_offset_starts_at_zero() {
+        assert_eq!(page_offset(1, 20), 0);
+        assert_eq!(page_offset(3, 20), 40);
+    }
+
+    #[test]
+    fn huge_page_does_not_overflow() {
+        assert_eq!(page_offset(i64::MAX, MAX_PAGE_SIZE), i64::MAX);
+    }
+}
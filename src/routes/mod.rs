@@ -1,15 +1,32 @@
+mod commands;
 mod ingest;
+mod leaderboards;
+mod matches;
+mod players;
+mod servers;
 mod webhooks;
 
 use crate::auth::auth;
+use crate::routes::commands::command_routes;
 use crate::routes::ingest::ingest_routes;
+use crate::routes::leaderboards::leaderboard_routes;
+use crate::routes::matches::match_routes;
+use crate::routes::players::player_routes;
+use crate::routes::servers::{list_dathost_servers, server_routes};
 use crate::routes::webhooks::webhook_routes;
 use crate::AppState;
+use axum::routing::get;
 use axum::{middleware, Router};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
         .nest("/webhooks", webhook_routes())
+        .nest("/players", player_routes())
+        .nest("/matches", match_routes())
+        .nest("/leaderboards", leaderboard_routes())
+        .nest("/servers", server_routes())
+        .route("/dathost/servers", get(list_dathost_servers))
+        .nest("/commands", command_routes())
         .layer(middleware::from_fn(auth))
         .nest("/ingest", ingest_routes())
 }
@@ -0,0 +1,23 @@
+use crate::db::get_player_stats;
+use crate::errors::Error;
+use crate::models::{PlayerStatsSummary, StatsParams};
+use crate::AppState;
+use axum::extract::{Path, Query, State};
+use axum::routing::get;
+use axum::{Json, Router};
+
+pub fn player_routes() -> Router<AppState> {
+    Router::new().route("/:steam_id/stats", get(player_stats))
+}
+
+pub async fn player_stats(
+    state: State<AppState>,
+    Path(steam_id): Path<u64>,
+    Query(params): Query<StatsParams>,
+) -> Result<Json<PlayerStatsSummary>, Error> {
+    let stats = get_player_stats(&state.db, Some(steam_id as i64), &params).await?;
+    let Some(stats) = stats.into_iter().next() else {
+        return Err(Error::NotFound);
+    };
+    Ok(Json(stats))
+}
@@ -0,0 +1,166 @@
+use crate::auth::generate_token;
+use crate::commands::{list_backups, restore_backup};
+use crate::db::{delete_server, get_registered_servers, register_server, rotate_server_token};
+use crate::errors::Error;
+use crate::models::{
+    DathostFile, RegisterServer, RegisteredServer, RestoreParams, RestoredBackup, ServerId,
+    ServerRegistration, ServerStatus,
+};
+use crate::AppState;
+use axum::extract::{Path, State};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use reqwest::StatusCode;
+
+pub fn server_routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_servers).post(create_server))
+        .route("/:server_id/token", post(rotate_token))
+        .route("/:server_id/backups", get(get_backups))
+        .route("/:server_id/restore", post(restore))
+        .route("/:server_id", delete(remove_server))
+        .route("/:server_id/status", get(server_status))
+        .route("/:server_id/start", post(start_server))
+        .route("/:server_id/stop", post(stop_server))
+        .route("/:server_id/restart", post(restart_server))
+        .route("/:server_id/duplicate", post(duplicate_server))
+}
+
+pub async fn list_servers(state: State<AppState>) -> Result<Json<Vec<ServerRegistration>>, Error> {
+    let servers = get_registered_servers(&state.db).await?;
+    Ok(Json(servers.into_iter().map(registration).collect()))
+}
+
+/// Registers a game server for log ingest, existing servers keep their token
+pub async fn create_server(
+    state: State<AppState>,
+    Json(server): Json<RegisterServer>,
+) -> Result<Json<ServerRegistration>, Error> {
+    let server = register_server(
+        &state.db,
+        &server.server_id,
+        server.name.as_deref(),
+        &generate_token(),
+    )
+    .await?;
+    tracing::info!("registered server {}", server.server_id);
+    Ok(Json(registration(server)))
+}
+
+pub async fn rotate_token(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerRegistration>, Error> {
+    let server = rotate_server_token(&state.db, &server_id, &generate_token()).await?;
+    tracing::info!("rotated ingest token for server {}", server.server_id);
+    Ok(Json(registration(server)))
+}
+
+pub async fn get_backups(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<Vec<DathostFile>>, Error> {
+    Ok(Json(list_backups(&state, &ServerId(server_id)).await?))
+}
+
+pub async fn restore(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+    Json(params): Json<RestoreParams>,
+) -> Result<Json<RestoredBackup>, Error> {
+    let file = restore_backup(&state, &ServerId(server_id.clone()), params.round).await?;
+    Ok(Json(RestoredBackup {
+        server_id,
+        round: params.round,
+        file,
+    }))
+}
+
+/// Every game server on the Dathost account, registered or not
+pub async fn list_dathost_servers(
+    state: State<AppState>,
+) -> Result<Json<Vec<ServerStatus>>, Error> {
+    let servers = state.dathost.list_servers().await?;
+    Ok(Json(servers.into_iter().map(ServerStatus::from).collect()))
+}
+
+pub async fn server_status(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerStatus>, Error> {
+    let server = state.dathost.get_server(&ServerId(server_id)).await?;
+    Ok(Json(server.into()))
+}
+
+pub async fn start_server(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerStatus>, Error> {
+    let server_id = ServerId(server_id);
+    tracing::info!("starting server {}", server_id);
+    dathost_ok(state.dathost.start_server(&server_id).await?)?;
+    Ok(Json(state.dathost.get_server(&server_id).await?.into()))
+}
+
+pub async fn stop_server(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerStatus>, Error> {
+    let server_id = ServerId(server_id);
+    tracing::info!("stopping server {}", server_id);
+    dathost_ok(state.dathost.stop_server(&server_id).await?)?;
+    Ok(Json(state.dathost.get_server(&server_id).await?.into()))
+}
+
+pub async fn restart_server(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerStatus>, Error> {
+    let server_id = ServerId(server_id);
+    tracing::info!("restarting server {}", server_id);
+    dathost_ok(state.dathost.restart_server(&server_id).await?)?;
+    Ok(Json(state.dathost.get_server(&server_id).await?.into()))
+}
+
+/// Copies a server, the copy has to be registered before its logs are accepted
+pub async fn duplicate_server(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<Json<ServerStatus>, Error> {
+    let server = state.dathost.duplicate_server(&ServerId(server_id)).await?;
+    tracing::info!("duplicated server as {}", server.id);
+    Ok(Json(server.into()))
+}
+
+/// Deletes the server on Dathost along with its log ingest registration
+pub async fn remove_server(
+    state: State<AppState>,
+    Path(server_id): Path<String>,
+) -> Result<StatusCode, Error> {
+    dathost_ok(
+        state
+            .dathost
+            .delete_server(&ServerId(server_id.clone()))
+            .await?,
+    )?;
+    delete_server(&state.db, &server_id).await?;
+    tracing::info!("deleted server {}", server_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn dathost_ok(status: StatusCode) -> Result<(), Error> {
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::Dathost(status))
+    }
+}
+
+fn registration(server: RegisteredServer) -> ServerRegistration {
+    ServerRegistration {
+        ingest_path: format!("/api/ingest/logs/{}", server.ingest_token),
+        server_id: server.server_id,
+        name: server.name,
+        ingest_token: server.ingest_token,
+    }
+}
@@ -1,16 +1,12 @@
-use crate::db::{create_match, create_match_stats};
+use crate::db::{enqueue_job, get_demo, get_match_by_dathost_id, record_match};
 use crate::errors::Error;
-use crate::models::{ActionRow, DathostMatchEnd, MatchEndParams, MessageComponent};
-use crate::utils::end_of_match_msg;
+use crate::models::{DathostMatchEnd, JobKind, MatchEndParams};
 use crate::AppState;
 use axum::extract::{Query, State};
-use axum::response::IntoResponse;
 use axum::routing::post;
 use axum::{Json, Router};
 use reqwest::StatusCode;
-use std::env;
-use std::time::Duration;
-use tokio::time::sleep;
+use sqlx::types::time::OffsetDateTime;
 
 pub fn webhook_routes() -> Router<AppState> {
     Router::new().route("/match-end", post(match_end))
@@ -20,60 +16,49 @@ pub async fn match_end(
     state: State<AppState>,
     query: Query<MatchEndParams>,
     dathost_match: Json<DathostMatchEnd>,
-) -> impl IntoResponse {
+) -> Result<StatusCode, Error> {
+    // cancelled matches are recorded too, flagged so they stay out of statistics
+    let cancelled = dathost_match.cancel_reason.is_some();
     if let Some(reason) = &dathost_match.cancel_reason {
-        tracing::info!("cancel reason: {}", reason);
-        return Ok(StatusCode::OK);
+        tracing::info!("match {} cancelled: {}", &dathost_match.id, reason);
     }
-    let created_match = create_match(&state.db, &dathost_match.0).await?;
-    create_match_stats(&state.db, &dathost_match.0, created_match.id).await?;
-    let delay = match query.wait_for_gotv {
-        Some(b) => b,
-        None => true,
+    // the match, its stats and the first job are committed together so a failure
+    // part way through never leaves a partially recorded match behind
+    let mut tx = state.db.begin().await?;
+    // Dathost retries failed webhooks, so a match we have already seen only resumes
+    // the steps that did not complete the first time around. A concurrent retry waits
+    // on the insert of the first one and then finds its row.
+    let created_match = match record_match(&mut tx, &dathost_match.0).await? {
+        Some(created_match) => {
+            state.live.reset(&dathost_match.server_id);
+            created_match
+        }
+        None => {
+            let existing = get_match_by_dathost_id(&mut *tx, &dathost_match.id)
+                .await?
+                .ok_or(Error::NotFound)?;
+            tracing::info!(
+                "match {} already recorded as {}, resuming",
+                &dathost_match.id,
+                existing.id
+            );
+            existing
+        }
     };
-    if delay {
-        let tv_delay = env::var("TV_DELAY")
-            .unwrap_or("105".to_string())
-            .parse::<u64>()
-            .unwrap()
-            + 30;
-        tracing::info!("sleeping for {} sec", tv_delay);
-        sleep(Duration::from_secs(tv_delay)).await;
-    }
-    tracing::info!("stopping server {}", &dathost_match.server_id);
-    let stop_status = state.dathost.stop_server(&dathost_match.server_id).await?;
-    if stop_status.as_u16() != 200 {
-        tracing::error!("error stopping server {}", stop_status);
-        return Err(Error::StopServerError);
-    }
-    let path = format!("{}.dem", dathost_match.id);
-    tracing::info!("fetching demo file '{}'", path);
-    let demo = state
-        .dathost
-        .get_file(&dathost_match.server_id, &path)
-        .await?;
-    tracing::info!("uploading demo to s3");
-    let s3_status = state.bucket.put_object(&path, &demo).await?.status_code();
-    if s3_status != 200 {
-        tracing::error!("s3 error: {}", s3_status);
-        return Err(Error::DemoUploadError);
-    }
-    let eom = end_of_match_msg(&state.steam, &dathost_match.0).await?;
-    let bucket_base_url = env::var("BUCKET_BASE_URL").expect("BUCKET_BASE_URL must be set");
-    let components = vec![ActionRow {
-        component_type: 1,
-        components: vec![MessageComponent {
-            component_type: 2,
-            label: "Download Demo".to_string(),
-            style: 5,
-            custom_id: None,
-            url: Some(format!("{}/{}", bucket_base_url, &path)),
-        }],
-    }];
-    tracing::info!("sending end of match message");
-    let discord_resp = state.discord.send_msg(&eom, components).await?;
-    if discord_resp.status() != 200 {
-        tracing::error!("discord error resp: {}", discord_resp.text().await?);
-    }
+    // the demo is fetched first, GOTV keeps writing it for a while after the match ends.
+    // Without GOTV the server is stopped right away and whatever demo there is uploaded.
+    let demo_pending = get_demo(&mut *tx, created_match.id).await?.is_none();
+    let kind = if demo_pending && !cancelled && query.wait_for_gotv.unwrap_or(true) {
+        JobKind::FetchDemo
+    } else if created_match.server_stopped_at.is_none() {
+        JobKind::StopServer
+    } else if created_match.summary_posted_at.is_none() {
+        JobKind::PostSummary
+    } else {
+        tx.commit().await?;
+        return Ok(StatusCode::OK);
+    };
+    enqueue_job(&mut *tx, created_match.id, kind, OffsetDateTime::now_utc()).await?;
+    tx.commit().await?;
     Ok(StatusCode::OK)
 }
@@ -0,0 +1,396 @@
+use crate::errors::Error;
+use crate::models::{DathostMatchEnd, SteamUser};
+use crate::utils::{find_mvp, team_rows, ScoreboardRow, TEAM1_COLOR, TEAM2_COLOR};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+
+pub const SCOREBOARD_FILE_NAME: &str = "scoreboard.png";
+
+static REGULAR_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+static BOLD_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+const WIDTH: u32 = 960;
+const PADDING: u32 = 24;
+const HEADER_HEIGHT: u32 = 96;
+const TEAM_BAR_HEIGHT: u32 = 40;
+const COLUMNS_HEIGHT: u32 = 30;
+const ROW_HEIGHT: u32 = 34;
+const TEAM_GAP: u32 = 16;
+const NAME_WIDTH: f32 = 360.0;
+
+const BACKGROUND: u32 = 0x1e1f22;
+const PANEL: u32 = 0x2b2d31;
+const ROW_EVEN: u32 = 0x2b2d31;
+const ROW_ODD: u32 = 0x313338;
+const TEXT: u32 = 0xf2f3f5;
+const MUTED: u32 = 0x949ba4;
+const MVP_COLOR: u32 = 0xf0b232;
+
+/// Stat columns, right aligned to the given x offset from the left edge of the board
+const COLUMNS: [(&str, f32); 8] = [
+    ("K", 440.0),
+    ("D", 500.0),
+    ("A", 560.0),
+    ("ADR", 650.0),
+    ("HS%", 736.0),
+    ("EF", 796.0),
+    ("ENT", 856.0),
+    ("1vX", 916.0),
+];
+
+/// Renders the end of match scoreboard as a PNG, each team in its own colour and sorted by
+/// damage with the MVP highlighted
+pub fn render_scoreboard(
+    dathost_match: &DathostMatchEnd,
+    steam_users: &[SteamUser],
+) -> Result<Vec<u8>, Error> {
+    let regular = FontRef::try_from_slice(REGULAR_FONT).expect("bundled font is valid");
+    let bold = FontRef::try_from_slice(BOLD_FONT).expect("bundled font is valid");
+    let mvp = find_mvp(dathost_match, steam_users).map(|row| row.steam_id);
+    let teams = [
+        (&dathost_match.team1, "team1", TEAM1_COLOR),
+        (&dathost_match.team2, "team2", TEAM2_COLOR),
+    ]
+    .map(|(team, key, color)| (team, team_rows(dathost_match, key, steam_users), color));
+    let height = PADDING * 2
+        + HEADER_HEIGHT
+        + teams
+            .iter()
+            .map(|(_, rows, _)| {
+                TEAM_GAP + TEAM_BAR_HEIGHT + COLUMNS_HEIGHT + rows.len() as u32 * ROW_HEIGHT
+            })
+            .sum::<u32>();
+    let mut canvas = Canvas::new(WIDTH, height, BACKGROUND);
+    let left = PADDING as f32;
+    let right = (WIDTH - PADDING) as f32;
+
+    // score header, the leading team's score in its colour
+    let (team1, team2) = (&dathost_match.team1, &dathost_match.team2);
+    let mut y = PADDING;
+    canvas.fill_rect(PADDING, y, WIDTH - PADDING * 2, HEADER_HEIGHT, PANEL);
+    let score = format!("{} : {}", team1.stats.score, team2.stats.score);
+    let score_width = canvas.text_width(&bold, 36.0, &score);
+    let center = WIDTH as f32 / 2.0;
+    let score_color = match team1.stats.score.cmp(&team2.stats.score) {
+        std::cmp::Ordering::Greater => TEAM1_COLOR,
+        std::cmp::Ordering::Less => TEAM2_COLOR,
+        std::cmp::Ordering::Equal => TEXT,
+    };
+    let baseline = y as f32 + 50.0;
+    canvas.draw_text(
+        &bold,
+        36.0,
+        center - score_width / 2.0,
+        baseline,
+        score_color,
+        &score,
+    );
+    let side_width = center - score_width / 2.0 - left - 40.0;
+    let name = fit(&canvas, &bold, 26.0, &team1.name, side_width);
+    let name_width = canvas.text_width(&bold, 26.0, &name);
+    canvas.draw_text(
+        &bold,
+        26.0,
+        center - score_width / 2.0 - 20.0 - name_width,
+        baseline,
+        TEAM1_COLOR,
+        &name,
+    );
+    let name = fit(&canvas, &bold, 26.0, &team2.name, side_width);
+    canvas.draw_text(
+        &bold,
+        26.0,
+        center + score_width / 2.0 + 20.0,
+        baseline,
+        TEAM2_COLOR,
+        &name,
+    );
+    let subtitle = format!(
+        "{} · {} rounds",
+        dathost_match.settings.map, dathost_match.rounds_played
+    );
+    let subtitle_width = canvas.text_width(&regular, 16.0, &subtitle);
+    canvas.draw_text(
+        &regular,
+        16.0,
+        center - subtitle_width / 2.0,
+        y as f32 + 80.0,
+        MUTED,
+        &subtitle,
+    );
+    y += HEADER_HEIGHT;
+
+    for (team, rows, color) in &teams {
+        y += TEAM_GAP;
+        canvas.fill_rect(PADDING, y, WIDTH - PADDING * 2, TEAM_BAR_HEIGHT, *color);
+        let baseline = y as f32 + 27.0;
+        let score = team.stats.score.to_string();
+        let score_width = canvas.text_width(&bold, 20.0, &score);
+        let name = fit(
+            &canvas,
+            &bold,
+            20.0,
+            &team.name,
+            right - left - score_width - 48.0,
+        );
+        canvas.draw_text(&bold, 20.0, left + 16.0, baseline, TEXT, &name);
+        canvas.draw_text(
+            &bold,
+            20.0,
+            right - 16.0 - score_width,
+            baseline,
+            TEXT,
+            &score,
+        );
+        y += TEAM_BAR_HEIGHT;
+
+        let baseline = y as f32 + 21.0;
+        canvas.draw_text(&bold, 14.0, left + 16.0, baseline, MUTED, "Player");
+        for (label, x) in COLUMNS {
+            let width = canvas.text_width(&bold, 14.0, label);
+            canvas.draw_text(&bold, 14.0, x - width, baseline, MUTED, label);
+        }
+        y += COLUMNS_HEIGHT;
+
+        for (i, row) in rows.iter().enumerate() {
+            let background = if i % 2 == 0 { ROW_EVEN } else { ROW_ODD };
+            canvas.fill_rect(PADDING, y, WIDTH - PADDING * 2, ROW_HEIGHT, background);
+            let is_mvp = mvp == Some(row.steam_id);
+            if is_mvp {
+                canvas.fill_rect(PADDING, y, 4, ROW_HEIGHT, MVP_COLOR);
+            }
+            draw_row(&mut canvas, &regular, &bold, row, y, is_mvp);
+            y += ROW_HEIGHT;
+        }
+    }
+    canvas.encode_png()
+}
+
+fn draw_row(
+    canvas: &mut Canvas,
+    regular: &FontRef,
+    bold: &FontRef,
+    row: &ScoreboardRow,
+    y: u32,
+    is_mvp: bool,
+) {
+    let left = PADDING as f32 + 16.0;
+    let baseline = y as f32 + 23.0;
+    let tag_width = if is_mvp {
+        canvas.text_width(bold, 12.0, "MVP") + 12.0
+    } else {
+        0.0
+    };
+    let name = fit(canvas, regular, 17.0, &row.name, NAME_WIDTH - tag_width);
+    let name_color = if is_mvp { MVP_COLOR } else { TEXT };
+    let name_width = canvas.draw_text(regular, 17.0, left, baseline, name_color, &name);
+    if is_mvp {
+        canvas.draw_text(
+            bold,
+            12.0,
+            left + name_width + 10.0,
+            baseline - 2.0,
+            MVP_COLOR,
+            "MVP",
+        );
+    }
+    let values = [
+        row.kills.to_string(),
+        row.deaths.to_string(),
+        row.assists.to_string(),
+        format!("{:.1}", row.adr),
+        format!("{:.0}%", row.hs_pct),
+        row.enemies_flashed.to_string(),
+        row.entries.to_string(),
+        row.clutches.to_string(),
+    ];
+    for (value, (_, x)) in values.iter().zip(COLUMNS) {
+        let width = canvas.text_width(regular, 17.0, value);
+        canvas.draw_text(regular, 17.0, x - width, baseline, TEXT, value);
+    }
+}
+
+/// Shortens `text` with an ellipsis until it fits in `max_width` pixels
+fn fit(canvas: &Canvas, font: &FontRef, size: f32, text: &str, max_width: f32) -> String {
+    if canvas.text_width(font, size, text) <= max_width {
+        return text.to_string();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.pop();
+        let candidate = format!("{}…", chars.iter().collect::<String>().trim_end());
+        if canvas.text_width(font, size, &candidate) <= max_width {
+            return candidate;
+        }
+    }
+    String::new()
+}
+
+/// An RGB pixel buffer
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: u32) -> Self {
+        let mut canvas = Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+        };
+        canvas.fill_rect(0, 0, width, height, background);
+        canvas
+    }
+
+    fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: u32) {
+        for py in y..(y + height).min(self.height) {
+            for px in x..(x + width).min(self.width) {
+                self.blend(px, py, color, 1.0);
+            }
+        }
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: u32, alpha: f32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let i = ((y * self.width + x) * 3) as usize;
+        for (c, pixel) in self.pixels[i..i + 3].iter_mut().enumerate() {
+            let channel = ((color >> (16 - c * 8)) & 0xff) as f32;
+            *pixel = (channel * alpha + *pixel as f32 * (1.0 - alpha)).round() as u8;
+        }
+    }
+
+    fn text_width(&self, font: &FontRef, size: f32, text: &str) -> f32 {
+        let font = font.as_scaled(PxScale::from(size));
+        let mut width = 0.0;
+        let mut last = None;
+        for c in text.chars() {
+            let id = font.glyph_id(c);
+            if let Some(last) = last {
+                width += font.kern(last, id);
+            }
+            width += font.h_advance(id);
+            last = Some(id);
+        }
+        width
+    }
+
+    /// Draws `text` with its baseline at `y`, returning the width drawn
+    fn draw_text(
+        &mut self,
+        font: &FontRef,
+        size: f32,
+        x: f32,
+        y: f32,
+        color: u32,
+        text: &str,
+    ) -> f32 {
+        let scaled = font.as_scaled(PxScale::from(size));
+        let mut caret = x;
+        let mut last = None;
+        for c in text.chars() {
+            let id = scaled.glyph_id(c);
+            if let Some(last) = last {
+                caret += scaled.kern(last, id);
+            }
+            let glyph = id.with_scale_and_position(size, point(caret, y));
+            caret += scaled.h_advance(id);
+            last = Some(id);
+            let Some(outline) = font.outline_glyph(glyph) else {
+                continue;
+            };
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px >= 0 && py >= 0 {
+                    self.blend(px as u32, py as u32, color, coverage.min(1.0));
+                }
+            });
+        }
+        caret - x
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    const MATCH_END: &str = include_str!("../tests/fixtures/match_end.json");
+    const STEAM_USERS: &str = include_str!("../tests/fixtures/steam_users.json");
+    const GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/scoreboard.png");
+
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        pixels.truncate(info.buffer_size());
+        (info.width, info.height, pixels)
+    }
+
+    /// Compares against the checked in image, run with `UPDATE_GOLDEN=1` after an intended
+    /// change to the layout to write it again
+    #[test]
+    fn matches_golden_image() {
+        let dathost_match: DathostMatchEnd = serde_json::from_str(MATCH_END).unwrap();
+        let steam_users: Vec<SteamUser> = serde_json::from_str(STEAM_USERS).unwrap();
+        let png = render_scoreboard(&dathost_match, &steam_users).unwrap();
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            fs::write(GOLDEN, &png).unwrap();
+        }
+        let golden = fs::read(GOLDEN).unwrap();
+        let (width, height, pixels) = decode(&png);
+        let (golden_width, golden_height, golden_pixels) = decode(&golden);
+        assert_eq!((width, height), (golden_width, golden_height));
+        let different = pixels
+            .chunks(3)
+            .zip(golden_pixels.chunks(3))
+            .filter(|(a, b)| a != b)
+            .count();
+        if different > 0 {
+            let actual = env::temp_dir().join("scoreboard.png");
+            fs::write(&actual, &png).unwrap();
+            panic!(
+                "{} pixels differ from the golden image, see {}",
+                different,
+                actual.display()
+            );
+        }
+    }
+
+    #[test]
+    fn fit_shortens_with_an_ellipsis() {
+        let canvas = Canvas::new(1, 1, BACKGROUND);
+        let font = FontRef::try_from_slice(REGULAR_FONT).unwrap();
+        let fit = |text: &str, max_width: f32| fit(&canvas, &font, 17.0, text, max_width);
+
+        assert_eq!(fit("", 100.0), "");
+        assert_eq!(fit("", 0.0), "");
+        assert_eq!(fit("Кирилл Смирнов", NAME_WIDTH), "Кирилл Смирнов");
+
+        let long = "A really very long nickname that never seems to end at all";
+        let fitted = fit(long, 120.0);
+        assert!(fitted.starts_with("A really"));
+        assert!(fitted.ends_with('…'));
+        assert!(canvas.text_width(&font, 17.0, &fitted) <= 120.0);
+        // a trailing space is dropped before the ellipsis
+        assert!(!fitted.contains(" …"));
+
+        // not even the ellipsis fits
+        assert_eq!(fit(long, 1.0), "");
+    }
+}
@@ -14,6 +14,9 @@ impl SteamClient {
     }
 
     pub async fn get_player_summaries(&self, steam_ids: Vec<u64>) -> Result<Vec<SteamUser>> {
+        if steam_ids.is_empty() {
+            return Ok(vec![]);
+        }
         let steam_key = env::var("STEAM_KEY").expect("STEAM_KEY must be set");
         let steam_ids: String = steam_ids
             .into_iter()
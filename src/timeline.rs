@@ -0,0 +1,103 @@
+use crate::db::{create_kill_events, create_round, delete_unlinked_rounds};
+use crate::errors::Error;
+use crate::live::{CompletedRound, KillEvent};
+use crate::logs::{LogEvent, LogLine, Side};
+use crate::models::ServerId;
+use crate::AppState;
+use sqlx::types::time::OffsetDateTime;
+
+/// Follows rounds and kills in a server's log stream, saving each round once it ends.
+/// Saved rounds are linked to the match when its match-end webhook arrives.
+pub async fn record_event(
+    state: &AppState,
+    server_id: &ServerId,
+    line: &LogLine,
+) -> Result<(), Error> {
+    let timestamp = line.timestamp.unwrap_or_else(OffsetDateTime::now_utc);
+    match &line.event {
+        LogEvent::MatchStart { map } => {
+            tracing::info!("match started on {} ({})", server_id, map);
+            state.live.reset(server_id);
+            state
+                .live
+                .lock()
+                .entry(server_id.clone())
+                .or_default()
+                .started = true;
+            // rounds from warmup or a restarted game never belong to a match
+            delete_unlinked_rounds(&state.db, server_id, 0).await?;
+        }
+        LogEvent::RoundStart => {
+            let mut live = state.live.lock();
+            let server = live.entry(server_id.clone()).or_default();
+            server.round_started_at = Some(timestamp);
+            server.round_kills.clear();
+        }
+        LogEvent::Kill {
+            attacker,
+            victim,
+            weapon,
+            headshot,
+        } => {
+            let mut live = state.live.lock();
+            live.entry(server_id.clone())
+                .or_default()
+                .round_kills
+                .push(KillEvent {
+                    attacker_steam_id: attacker.steam_id,
+                    victim_steam_id: victim.steam_id,
+                    weapon: weapon.clone(),
+                    headshot: *headshot,
+                    killed_at: timestamp,
+                });
+        }
+        LogEvent::RoundEnd {
+            winner,
+            reason,
+            ct_score,
+            t_score,
+        } => {
+            let (started_at, kills) = {
+                let mut live = state.live.lock();
+                let server = live.entry(server_id.clone()).or_default();
+                server.ct_score = *ct_score;
+                server.t_score = *t_score;
+                (
+                    server.round_started_at.take(),
+                    std::mem::take(&mut server.round_kills),
+                )
+            };
+            let round = CompletedRound {
+                round_number: ct_score + t_score,
+                winner: match winner {
+                    Side::CounterTerrorist => "CT",
+                    Side::Terrorist => "T",
+                    _ => "none",
+                }
+                .to_string(),
+                win_reason: reason.clone(),
+                ct_score: *ct_score,
+                t_score: *t_score,
+                started_at,
+                ended_at: timestamp,
+            };
+            let mut tx = state.db.begin().await?;
+            // a restored backup replays rounds, the replayed ones replace the originals
+            delete_unlinked_rounds(&mut *tx, server_id, round.round_number).await?;
+            let round_id = create_round(&mut *tx, server_id, &round).await?;
+            create_kill_events(&mut *tx, round_id, &kills).await?;
+            tx.commit().await?;
+        }
+        LogEvent::MatchStatus {
+            ct_score, t_score, ..
+        } => {
+            let mut live = state.live.lock();
+            let server = live.entry(server_id.clone()).or_default();
+            server.ct_score = *ct_score;
+            server.t_score = *t_score;
+        }
+        LogEvent::GameOver { .. } => state.live.reset(server_id),
+        _ => {}
+    }
+    Ok(())
+}
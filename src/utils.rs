@@ -1,50 +1,57 @@
 use crate::errors::Error;
-use crate::models::{DathostMatchEnd, Player, SteamUser};
-use crate::steam::SteamClient;
+use crate::models::{
+    DathostMatchEnd, Embed, EmbedField, EmbedFooter, EmbedImage, Player, SteamUser,
+};
+use reqwest::Url;
+use s3::Bucket;
+use sqlx::types::time::OffsetDateTime;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub async fn end_of_match_msg(
-    steam: &SteamClient,
-    dathost_match: &DathostMatchEnd,
-) -> Result<String, Error> {
-    let steam_ids: Vec<u64> = dathost_match
-        .players
-        .iter()
-        .map(|p| p.steam_id_64)
-        .collect();
-    let steam_users: Vec<SteamUser> = steam.get_player_summaries(steam_ids).await?;
-    let mut team1_players: Vec<&Player> = dathost_match
-        .players
-        .iter()
-        .filter(|p| p.team == "team1")
-        .collect();
-    team1_players.sort_by(|a, b| b.stats.damage_dealt.cmp(&a.stats.damage_dealt));
-    let mut team2_players: Vec<&Player> = dathost_match
-        .players
-        .iter()
-        .filter(|p| p.team == "team2")
-        .collect();
-    team2_players.sort_by(|a, b| b.stats.damage_dealt.cmp(&a.stats.damage_dealt));
-    let mvp = if team1_players.first().unwrap().stats.damage_dealt
-        > team2_players.first().unwrap().stats.damage_dealt
-    {
-        (
-            steam_users
-                .iter()
-                .find(|u| u.steamid == team1_players.first().unwrap().steam_id_64)
-                .unwrap(),
-            team1_players.first().unwrap().stats.damage_dealt as f32
-                / dathost_match.rounds_played as f32,
-        )
-    } else {
-        (
-            steam_users
-                .iter()
-                .find(|u| u.steamid == team2_players.first().unwrap().steam_id_64)
-                .unwrap(),
-            team2_players.first().unwrap().stats.damage_dealt as f32
-                / dathost_match.rounds_played as f32,
-        )
-    };
+/// Teams swap sides every half, so each keeps its own colour for the whole match
+pub const TEAM1_COLOR: u32 = 0x5865f2;
+pub const TEAM2_COLOR: u32 = 0xeb459e;
+const DRAW_COLOR: u32 = 0x99aab5;
+
+/// Checks a base URL such as `PUBLIC_URL`, paths are appended to it as they are
+pub fn parse_public_url(url: &str) -> Result<String, String> {
+    let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("'{}' must start with http:// or https://", url));
+    }
+    if url.ends_with('/') {
+        return Err(format!("'{}' must not end with a slash", url));
+    }
+    Ok(url.to_string())
+}
+
+/// Reads `key` from the environment, `None` when it is unset and an error when it is set
+/// to something that doesn't parse
+pub fn parse_env<T: FromStr>(key: &str) -> Result<Option<T>, String> {
+    match env::var(key) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("'{}' can't be parsed", value)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Reads `key` from the environment, falling back to `default` when it is unset or invalid
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    parse_env(key)
+        .unwrap_or_else(|e| {
+            tracing::warn!("invalid {}: {}, using the default", key, e);
+            None
+        })
+        .unwrap_or(default)
+}
+
+/// The scoreboard as a code block, players without a Steam profile show up by their
+/// steam id
+pub fn end_of_match_msg(dathost_match: &DathostMatchEnd, steam_users: &[SteamUser]) -> String {
     let mut msg = String::new();
     msg.push_str(
         format!(
@@ -58,68 +65,327 @@ pub async fn end_of_match_msg(
     msg.push_str("```md\n");
     msg.push_str("   Player              K   D   A   ADR     HS%     EF   ENT  1vX\n");
     msg.push_str("----------------------------------------------------------------\n");
-    msg.push_str(dathost_match.team1.name.as_str());
-    msg.push_str("\n");
-    for (i, p) in team1_players.iter().enumerate() {
-        msg.push_str(scoreboard_row(p, dathost_match.rounds_played, &steam_users, i + 1).as_str())
-    }
-    msg.push_str("\n");
-    msg.push_str(dathost_match.team2.name.as_str());
-    msg.push_str("\n");
-    for (i, p) in team2_players.iter().enumerate() {
-        msg.push_str(scoreboard_row(p, dathost_match.rounds_played, &steam_users, i + 1).as_str())
-    }
+    let teams: Vec<String> = [
+        ("team1", &dathost_match.team1),
+        ("team2", &dathost_match.team2),
+    ]
+    .into_iter()
+    .map(|(key, team)| {
+        let mut table = format!("{}\n", team.name);
+        for (i, row) in team_rows(dathost_match, key, steam_users)
+            .iter()
+            .enumerate()
+        {
+            table.push_str(scoreboard_row(row, i + 1).as_str())
+        }
+        table
+    })
+    .collect();
+    msg.push_str(teams.join("\n").as_str());
     msg.push_str("```\n");
-    msg.push_str("\n");
-    msg.push_str(
+    if let Some(mvp) = find_mvp(dathost_match, steam_users) {
+        msg.push('\n');
+        msg.push_str(
+            format!(
+                "Congrats to the MVP `{}` with the highest ADR of `{:.1}`!\n",
+                mvp.name, mvp.adr
+            )
+            .as_str(),
+        );
+    }
+    msg
+}
+
+/// The same scoreboard as `end_of_match_msg` as an embed, one field per team,
+/// in the colour of the winning team.
+pub fn end_of_match_embed(
+    dathost_match: &DathostMatchEnd,
+    steam_users: &[SteamUser],
+    completed_at: OffsetDateTime,
+    duration: Option<Duration>,
+) -> Embed {
+    let (team1, team2) = (&dathost_match.team1, &dathost_match.team2);
+    let color = match team1.stats.score.cmp(&team2.stats.score) {
+        std::cmp::Ordering::Greater => TEAM1_COLOR,
+        std::cmp::Ordering::Less => TEAM2_COLOR,
+        std::cmp::Ordering::Equal => DRAW_COLOR,
+    };
+    let mvp = find_mvp(dathost_match, steam_users).map(|row| {
         format!(
-            "Congrats to the MVP `{}` with the highest ADR of `{:2}`!\n",
-            mvp.0.personaname, mvp.1
+            "Congrats to the MVP **{}** with the highest ADR of `{:.1}`!",
+            escape_markdown(&row.name),
+            row.adr
         )
-        .as_str(),
-    );
-    Ok(msg)
+    });
+    let fields = [("team1", team1), ("team2", team2)]
+        .into_iter()
+        .map(|(key, team)| {
+            let value = team_rows(dathost_match, key, steam_users)
+                .iter()
+                .map(|row| {
+                    format!(
+                        "**{}** {}/{}/{} · {:.1} ADR · {:.0}% HS",
+                        escape_markdown(truncate(&row.name, 24)),
+                        row.kills,
+                        row.deaths,
+                        row.assists,
+                        row.adr,
+                        row.hs_pct,
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            EmbedField {
+                name: format!("{} — {}", team.name, team.stats.score),
+                value: if value.is_empty() {
+                    "No players".to_string()
+                } else {
+                    value
+                },
+                inline: false,
+            }
+        })
+        .collect();
+    let mut footer = format!("{} rounds", dathost_match.rounds_played);
+    if let Some(duration) = duration {
+        let minutes = duration.as_secs() / 60;
+        footer = format!("{}h {:02}m · {}", minutes / 60, minutes % 60, footer);
+    }
+    Embed {
+        title: Some(format!(
+            "{} {} - {} {}",
+            team1.name, team1.stats.score, team2.stats.score, team2.name
+        )),
+        description: Some(match mvp {
+            Some(mvp) => format!("`{}`\n{}", dathost_match.settings.map, mvp),
+            None => format!("`{}`", dathost_match.settings.map),
+        }),
+        color: Some(color),
+        fields,
+        thumbnail: map_image_url(&dathost_match.settings.map).map(|url| EmbedImage { url }),
+        image: None,
+        footer: Some(EmbedFooter { text: footer }),
+        timestamp: Some(completed_at),
+    }
 }
 
-fn scoreboard_row(
-    p: &Player,
-    rounds_played: i32,
-    steam_users: &Vec<SteamUser>,
-    i: usize,
-) -> String {
-    let name = steam_users
+/// `MAP_IMAGE_URL` is a template such as `https://example.com/maps/{map}.png`
+fn map_image_url(map: &str) -> Option<String> {
+    env::var("MAP_IMAGE_URL")
+        .ok()
+        .filter(|t| !t.is_empty())
+        .map(|t| t.replace("{map}", map))
+}
+
+/// The per-player columns of the scoreboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreboardRow {
+    pub steam_id: u64,
+    pub name: String,
+    pub kills: i32,
+    pub deaths: i32,
+    pub assists: i32,
+    pub adr: f32,
+    pub hs_pct: f32,
+    pub enemies_flashed: i32,
+    pub entries: i32,
+    pub clutches: i32,
+}
+
+impl ScoreboardRow {
+    /// Falls back to the steam id for the name when the profile could not be looked up
+    pub fn new(p: &Player, rounds_played: i32, steam_users: &[SteamUser]) -> Self {
+        Self {
+            steam_id: p.steam_id_64,
+            name: steam_users
+                .iter()
+                .find(|u| u.steamid == p.steam_id_64)
+                .map(|u| u.personaname.clone())
+                .unwrap_or_else(|| p.steam_id_64.to_string()),
+            kills: p.stats.kills,
+            deaths: p.stats.deaths,
+            assists: p.stats.assists,
+            adr: p.stats.damage_dealt as f32 / rounds_played.max(1) as f32,
+            hs_pct: (p.stats.kills_with_headshot as f32 / p.stats.kills.max(1) as f32) * 100.0,
+            enemies_flashed: p.stats.flashes_enemies_blinded,
+            entries: p.stats.entry_successes,
+            clutches: p.stats.n1v_x_wins,
+        }
+    }
+}
+
+/// The rows of `team` ("team1" or "team2"), highest damage first
+pub fn team_rows(
+    dathost_match: &DathostMatchEnd,
+    team: &str,
+    steam_users: &[SteamUser],
+) -> Vec<ScoreboardRow> {
+    let mut players: Vec<&Player> = dathost_match
+        .players
+        .iter()
+        .filter(|p| p.team == team)
+        .collect();
+    players.sort_by_key(|p| std::cmp::Reverse(p.stats.damage_dealt));
+    players
+        .into_iter()
+        .map(|p| ScoreboardRow::new(p, dathost_match.rounds_played, steam_users))
+        .collect()
+}
+
+/// The player with the highest ADR across both teams
+pub fn find_mvp(
+    dathost_match: &DathostMatchEnd,
+    steam_users: &[SteamUser],
+) -> Option<ScoreboardRow> {
+    dathost_match
+        .players
         .iter()
-        .find(|u| u.steamid == p.steam_id_64)
-        .unwrap()
-        .personaname
-        .to_string();
-    let name = format!("{:<19}", name);
+        .max_by_key(|p| p.stats.damage_dealt)
+        .map(|p| ScoreboardRow::new(p, dathost_match.rounds_played, steam_users))
+}
+
+fn scoreboard_row(row: &ScoreboardRow, i: usize) -> String {
+    let name = format!("{:<19}", row.name);
     let name = truncate(name.as_str(), 19);
-    let adr = format!(
-        "{:.1}",
-        (p.stats.damage_dealt as f32 / rounds_played.max(1) as f32)
-    );
-    let hs = format!(
-        "{:.1}%",
-        (p.stats.kills_with_headshot as f32 / p.stats.kills.max(1) as f32) * 100.0,
-    );
+    let adr = format!("{:.1}", row.adr);
+    let hs = format!("{:.1}%", row.hs_pct);
     format!(
         "{i}. {:<20}{:<4}{:<4}{:<4}{:<8}{:<8}{:<5}{:<5}{:<5}\n",
         name,
-        p.stats.kills,
-        p.stats.deaths,
-        p.stats.assists,
+        row.kills,
+        row.deaths,
+        row.assists,
         adr,
         hs,
-        p.stats.flashes_enemies_blinded,
-        p.stats.entry_successes,
-        p.stats.n1v_x_wins,
+        row.enemies_flashed,
+        row.entries,
+        row.clutches,
     )
 }
 
+/// Escapes the characters Discord would otherwise read as formatting, so a player
+/// named e.g. `**x**` doesn't break out of the bold around their name
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '-' | '[' | ']' | '(' | ')'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 fn truncate(s: &str, max_chars: usize) -> &str {
     match s.char_indices().nth(max_chars) {
         None => s,
         Some((idx, _)) => &s[..idx],
     }
 }
+
+/// Presigned URLs can not outlive a week
+pub const MAX_PRESIGN_SECS: u32 = 7 * 24 * 60 * 60;
+
+/// A temporary download link for a demo, so the bucket itself can stay private
+pub fn presign_demo(bucket: &Bucket, object_key: &str, expiry_secs: u32) -> Result<String, Error> {
+    Ok(bucket.presign_get(object_key, expiry_secs.min(MAX_PRESIGN_SECS), None)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATCH_END: &str = include_str!("../tests/fixtures/match_end.json");
+    const STEAM_USERS: &str = include_str!("../tests/fixtures/steam_users.json");
+
+    fn fixtures() -> (DathostMatchEnd, Vec<SteamUser>) {
+        (
+            serde_json::from_str(MATCH_END).unwrap(),
+            serde_json::from_str(STEAM_USERS).unwrap(),
+        )
+    }
+
+    fn embed() -> Embed {
+        let (dathost_match, steam_users) = fixtures();
+        end_of_match_embed(
+            &dathost_match,
+            &steam_users,
+            OffsetDateTime::UNIX_EPOCH,
+            None,
+        )
+    }
+
+    #[test]
+    fn public_url_needs_a_scheme_and_no_trailing_slash() {
+        assert_eq!(
+            parse_public_url("https://scrimbot.example.com/bot"),
+            Ok("https://scrimbot.example.com/bot".to_string())
+        );
+        assert!(parse_public_url("scrimbot.example.com").is_err());
+        assert!(parse_public_url("scrimbot.example.com:3000").is_err());
+        assert!(parse_public_url("https://scrimbot.example.com/").is_err());
+    }
+
+    #[test]
+    fn env_values_fall_back_to_the_default() {
+        env::set_var("SCRIMBOT_TEST_ENV_VALID", " 42 ");
+        env::set_var("SCRIMBOT_TEST_ENV_INVALID", "-1");
+        assert_eq!(parse_env::<u32>("SCRIMBOT_TEST_ENV_VALID"), Ok(Some(42)));
+        assert_eq!(parse_env::<u32>("SCRIMBOT_TEST_ENV_UNSET"), Ok(None));
+        assert!(parse_env::<u32>("SCRIMBOT_TEST_ENV_INVALID").is_err());
+        assert_eq!(env_or("SCRIMBOT_TEST_ENV_UNSET", 5u32), 5);
+        assert_eq!(env_or("SCRIMBOT_TEST_ENV_INVALID", 5u32), 5);
+    }
+
+    #[test]
+    fn embed_is_in_the_winning_teams_colour() {
+        // team two wins 13 - 11 after ending the match on CT
+        assert_eq!(embed().color, Some(TEAM2_COLOR));
+    }
+
+    #[test]
+    fn player_names_are_escaped() {
+        let embed = embed();
+        let team1 = &embed.fields[0].value;
+        // names are cut to 24 characters before they are escaped
+        assert!(team1.contains(r"**\*\*not bold\*\* \_or\_ \`code\`**"));
+        assert!(team1.contains("**A really very long nickn**"));
+        assert!(team1.contains("**Кирилл Смирнов**"));
+        assert!(team1.contains("**76561198000000005**"));
+        let team2 = &embed.fields[1].value;
+        assert!(team2.contains(r"**\[link\]\(http://x.co\)**"));
+        assert!(team2.contains(r"**back\\slash \> quote**"));
+        assert!(embed.description.unwrap().contains("MVP **Дмитрий**"));
+    }
+
+    #[test]
+    fn escapes_after_truncating() {
+        assert_eq!(escape_markdown(truncate("a_b", 2)), r"a\_");
+        assert_eq!(escape_markdown(""), "");
+    }
+
+    #[test]
+    fn text_scoreboard_shows_unresolved_players_by_steam_id() {
+        let (dathost_match, steam_users) = fixtures();
+        let msg = end_of_match_msg(&dathost_match, &steam_users);
+        assert!(msg.contains("5. 76561198000000005 "));
+        assert!(msg.contains("MVP `Дмитрий` with the highest ADR of `130.0`"));
+        // nobody resolved, including the MVP
+        let msg = end_of_match_msg(&dathost_match, &[]);
+        assert!(msg.contains("MVP `76561198000000006`"));
+    }
+
+    #[test]
+    fn text_scoreboard_allows_an_empty_team() {
+        let (mut dathost_match, steam_users) = fixtures();
+        dathost_match.players.retain(|p| p.team == "team1");
+        let msg = end_of_match_msg(&dathost_match, &steam_users);
+        assert!(msg.contains("MVP `ANN`"));
+        dathost_match.players.clear();
+        let msg = end_of_match_msg(&dathost_match, &steam_users);
+        assert!(!msg.contains("MVP"));
+    }
+}
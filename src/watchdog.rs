@@ -0,0 +1,100 @@
+use crate::db::get_registered_servers;
+use crate::errors::Error;
+use crate::models::{DathostServer, ServerId};
+use crate::utils::env_or;
+use crate::AppState;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// Stops registered servers that have been online without players for
+/// `IDLE_SHUTDOWN_MINUTES`, e.g. after a cancelled match or one nobody joined.
+pub async fn run_watchdog(state: AppState) {
+    let idle_limit: u64 = env_or("IDLE_SHUTDOWN_MINUTES", 15);
+    if idle_limit == 0 {
+        tracing::info!("idle server watchdog disabled");
+        return;
+    }
+    let idle_limit = Duration::from_secs(idle_limit.saturating_mul(60));
+    let interval = env_or("IDLE_CHECK_SECONDS", 60);
+    // when each server was first seen online and empty
+    let mut idle_since: HashMap<ServerId, Instant> = HashMap::new();
+    loop {
+        sleep(Duration::from_secs(interval)).await;
+        if let Err(e) = check_servers(&state, &mut idle_since, idle_limit).await {
+            tracing::error!("idle server check failed: {}", e);
+        }
+    }
+}
+
+async fn check_servers(
+    state: &AppState,
+    idle_since: &mut HashMap<ServerId, Instant>,
+    idle_limit: Duration,
+) -> Result<(), Error> {
+    let registered: HashSet<String> = get_registered_servers(&state.db)
+        .await?
+        .into_iter()
+        .map(|s| s.server_id)
+        .collect();
+    let servers: Vec<DathostServer> = state
+        .dathost
+        .list_servers()
+        .await?
+        .into_iter()
+        .filter(|s| registered.contains(&s.id))
+        .collect();
+    let now = Instant::now();
+    let idle: HashSet<ServerId> = servers
+        .iter()
+        .filter(|s| s.on && !s.booting && s.players_online == 0)
+        .map(|s| ServerId(s.id.clone()))
+        .collect();
+    idle_since.retain(|server_id, _| idle.contains(server_id));
+    for server in servers {
+        let server_id = ServerId(server.id.clone());
+        if !idle.contains(&server_id) {
+            continue;
+        }
+        let since = *idle_since.entry(server_id.clone()).or_insert(now);
+        if now.duration_since(since) < idle_limit {
+            continue;
+        }
+        tracing::info!("stopping idle server {}", server_id);
+        // a failure on one server shouldn't keep the rest running
+        match state.dathost.stop_server(&server_id).await {
+            Ok(status) if status.is_success() => {}
+            Ok(status) => {
+                tracing::error!("error stopping idle server {}: {}", server_id, status);
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("error stopping idle server {}: {}", server_id, e);
+                continue;
+            }
+        }
+        idle_since.remove(&server_id);
+        state.live.reset(&server_id);
+        let minutes = now.duration_since(since).as_secs() / 60;
+        let msg = format!(
+            "Stopped server **{}** after {} minutes without players.",
+            if server.name.is_empty() {
+                &server.id
+            } else {
+                &server.name
+            },
+            minutes
+        );
+        match state.discord.send_msg(&msg, vec![], vec![]).await {
+            Ok(discord_resp) if discord_resp.status() != 200 => {
+                tracing::error!(
+                    "discord error resp: {}",
+                    discord_resp.text().await.unwrap_or_default()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("unable to post idle shutdown of {}: {}", server_id, e),
+        }
+    }
+    Ok(())
+}